@@ -140,6 +140,34 @@ fn main() {
 
 
 
+    /*** SOUNDS ***/
+    // Same as with the textures, adding or removing files doesn't trigger a rebuild
+    println!("cargo:rerun-if-changed=sounds/touch-to-rebuild-sounds");
+    let mut out_file = File::create(out_dir.join("sounds.rs")).unwrap();
+    let mut music = Vec::new();
+    for entry in walkdir::WalkDir::new("sounds") {
+        let entry = entry.unwrap();
+        if entry.path().extension().map_or(false, |ext|ext=="wav") {
+            let name = entry.path().file_stem().unwrap().to_string_lossy().to_string();
+            let path = std::fs::canonicalize(entry.path()).unwrap();
+            if entry.path().parent().map_or(false, |parent|parent.ends_with("music")) {
+                music.push((name, path));
+            } else {
+                writeln!(out_file, "pub const {}: &[u8] = include_bytes!({:?});", name.to_uppercase(), path).unwrap();
+            }
+        }
+    }
+    writeln!(out_file, "/// Tracks in sounds/music by file name").unwrap();
+    writeln!(out_file, "pub fn music(name: &str) -> Option<&'static [u8]> {{").unwrap();
+    writeln!(out_file, "    match name {{").unwrap();
+    for (name, path) in music {
+        writeln!(out_file, "        {:?} => Some(&include_bytes!({:?})[..]),", name, path).unwrap();
+    }
+    writeln!(out_file, "        _ => None").unwrap();
+    writeln!(out_file, "    }}").unwrap();
+    writeln!(out_file, "}}").unwrap();
+
+
     /*** SHADERS ***/
     let out_dir = Path::new(&env::var("OUT_DIR").unwrap()).join("shaders");
     std::fs::create_dir_all(&out_dir).unwrap();
@@ -0,0 +1,53 @@
+use std::io::Cursor;
+use rodio::{Device, Sink, Decoder, Source};
+
+/// Plays through rodio on the default output device
+pub struct Native {
+    device: Device,
+    music: Option<Sink>
+}
+
+impl Native {
+    pub fn new() -> Option<Self> {
+        Some(Native {
+            device: rodio::default_output_device()?,
+            music: None
+        })
+    }
+}
+
+fn decode(data: &'static [u8]) -> Option<Decoder<Cursor<&'static [u8]>>> {
+    match Decoder::new(Cursor::new(data)) {
+        Ok(decoder) => Some(decoder),
+        Err(err) => {
+            println!("Failed to decode sound: {}", err);
+            None
+        }
+    }
+}
+
+impl super::Backend for Native {
+    fn play(&mut self, data: &'static [u8], volume: f32) {
+        if let Some(source) = decode(data) {
+            rodio::play_raw(&self.device, source.amplify(volume).convert_samples());
+        }
+    }
+
+    fn play_music(&mut self, data: Option<&'static [u8]>, volume: f32) {
+        if let Some(music) = self.music.take() {
+            music.stop();
+        }
+        if let Some(source) = data.and_then(decode) {
+            let sink = Sink::new(&self.device);
+            sink.set_volume(volume);
+            sink.append(source.repeat_infinite());
+            self.music = Some(sink);
+        }
+    }
+
+    fn set_music_volume(&mut self, volume: f32) {
+        if let Some(music) = &self.music {
+            music.set_volume(volume);
+        }
+    }
+}
@@ -0,0 +1,10 @@
+/// Plays nothing, for when there's no audio device or no need for one
+pub struct Null;
+
+impl super::Backend for Null {
+    fn play(&mut self, _data: &'static [u8], _volume: f32) {}
+
+    fn play_music(&mut self, _data: Option<&'static [u8]>, _volume: f32) {}
+
+    fn set_music_volume(&mut self, _volume: f32) {}
+}
@@ -0,0 +1,110 @@
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use wasm_bindgen::{JsCast, JsValue, closure::Closure};
+use web_sys::{AudioContext, AudioBuffer, AudioBufferSourceNode, AudioScheduledSourceNode, GainNode};
+
+/// Plays through WebAudio, which decodes asynchronously, so sounds start once they're decoded
+pub struct WebAudio {
+    context: AudioContext,
+    /// By the address of the embedded data
+    buffers: Rc<RefCell<HashMap<usize, AudioBuffer>>>,
+    music: Rc<RefCell<Option<(AudioBufferSourceNode, GainNode)>>>,
+    /// Address of the track that should play once decoded, 0 for none
+    wanted_music: Rc<Cell<usize>>,
+    music_volume: Rc<Cell<f32>>
+}
+
+impl WebAudio {
+    pub fn new() -> Option<Self> {
+        Some(WebAudio {
+            context: AudioContext::new().ok()?,
+            buffers: Rc::new(RefCell::new(HashMap::new())),
+            music: Rc::new(RefCell::new(None)),
+            wanted_music: Rc::new(Cell::new(0)),
+            music_volume: Rc::new(Cell::new(1.0))
+        })
+    }
+
+    /// Right away if it was decoded before
+    fn with_buffer(&self, data: &'static [u8], callback: impl FnOnce(&AudioBuffer) + 'static) {
+        let key = data.as_ptr() as usize;
+        if let Some(buffer) = self.buffers.borrow().get(&key) {
+            callback(buffer);
+            return
+        }
+        let buffers = self.buffers.clone();
+        let on_decoded = Closure::once_into_js(move |buffer: AudioBuffer| {
+            callback(&buffer);
+            buffers.borrow_mut().insert(key, buffer);
+        });
+        // Decoding detaches the array buffer, so it gets a copy
+        let array = js_sys::Uint8Array::from(data);
+        if let Err(err) = self.context.decode_audio_data_with_success_callback(&array.buffer(), on_decoded.unchecked_ref()) {
+            log_error(err);
+        }
+    }
+}
+
+fn start(context: &AudioContext, buffer: &AudioBuffer, volume: f32, looping: bool) -> Result<(AudioBufferSourceNode, GainNode), JsValue> {
+    let source = context.create_buffer_source()?;
+    source.set_buffer(Some(buffer));
+    source.set_loop(looping);
+    let gain = context.create_gain()?;
+    gain.gain().set_value(volume);
+    source.connect_with_audio_node(&gain)?;
+    gain.connect_with_audio_node(&context.destination())?;
+    let scheduled: &AudioScheduledSourceNode = &source;
+    scheduled.start()?;
+    Ok((source, gain))
+}
+
+fn log_error(err: JsValue) {
+    web_sys::console::log_2(&"Failed to play sound:".into(), &err);
+}
+
+impl super::Backend for WebAudio {
+    fn play(&mut self, data: &'static [u8], volume: f32) {
+        // Browsers keep the context suspended until the player interacts with the page
+        let _ = self.context.resume();
+        let context = self.context.clone();
+        self.with_buffer(data, move |buffer| {
+            if let Err(err) = start(&context, buffer, volume, false) {
+                log_error(err);
+            }
+        });
+    }
+
+    fn play_music(&mut self, data: Option<&'static [u8]>, volume: f32) {
+        let _ = self.context.resume();
+        if let Some((source, _)) = self.music.borrow_mut().take() {
+            let scheduled: &AudioScheduledSourceNode = &source;
+            let _ = scheduled.stop();
+        }
+        let key = data.map_or(0, |data|data.as_ptr() as usize);
+        self.wanted_music.set(key);
+        self.music_volume.set(volume);
+        if let Some(data) = data {
+            let context = self.context.clone();
+            let music = self.music.clone();
+            let wanted_music = self.wanted_music.clone();
+            let music_volume = self.music_volume.clone();
+            self.with_buffer(data, move |buffer| {
+                // Other music might have been chosen while this was decoding
+                if wanted_music.get() == key {
+                    match start(&context, buffer, music_volume.get(), true) {
+                        Ok(nodes) => *music.borrow_mut() = Some(nodes),
+                        Err(err) => log_error(err)
+                    }
+                }
+            });
+        }
+    }
+
+    fn set_music_volume(&mut self, volume: f32) {
+        self.music_volume.set(volume);
+        if let Some((_, gain)) = &*self.music.borrow() {
+            gain.gain().set_value(volume);
+        }
+    }
+}
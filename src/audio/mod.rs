@@ -0,0 +1,126 @@
+use crate::storage;
+
+#[cfg(target_arch="wasm32")]
+mod backend_webaudio;
+#[cfg(not(target_arch="wasm32"))]
+mod backend_native;
+mod backend_null;
+
+mod sounds {
+    include!(concat!(env!("OUT_DIR"), "/sounds.rs"));
+}
+
+const VOLUME_SAVE_NAME: &str = "cary_volume";
+/// Played if a level doesn't choose any, "none" is silence
+pub const DEFAULT_MUSIC: &str = "theme";
+
+/// Events the simulation makes a noise for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sound {
+    Flap,
+    PickUp,
+    Drop,
+    /// A walker turning around
+    Turn,
+    /// A walker jumping
+    Jump,
+    Shoot,
+    /// A bullet hitting something
+    Impact,
+    Death,
+    Victory
+}
+
+impl Sound {
+    fn data(self) -> &'static [u8] {
+        match self {
+            Sound::Flap => sounds::FLAP,
+            Sound::PickUp => sounds::PICK_UP,
+            Sound::Drop => sounds::DROP,
+            Sound::Turn => sounds::TURN,
+            Sound::Jump => sounds::JUMP,
+            Sound::Shoot => sounds::SHOOT,
+            Sound::Impact => sounds::IMPACT,
+            Sound::Death => sounds::DEATH,
+            Sound::Victory => sounds::VICTORY
+        }
+    }
+}
+
+/// Plays the embedded wav files, one implementation per platform
+trait Backend {
+    fn play(&mut self, data: &'static [u8], volume: f32);
+    /// Loops until other music is started, None stops it
+    fn play_music(&mut self, data: Option<&'static [u8]>, volume: f32);
+    fn set_music_volume(&mut self, volume: f32);
+}
+
+pub struct Audio {
+    backend: Box<dyn Backend>,
+    /// Of everything, set by the player
+    volume: f32,
+    /// Name and volume of the track playing
+    music: Option<(String, f32)>
+}
+
+impl Audio {
+    /// Stays silent if there's no audio device
+    pub fn new() -> Self {
+        #[cfg(target_arch="wasm32")]
+        let backend = backend_webaudio::WebAudio::new().map(|backend|Box::new(backend) as Box<dyn Backend>);
+        #[cfg(not(target_arch="wasm32"))]
+        let backend = backend_native::Native::new().map(|backend|Box::new(backend) as Box<dyn Backend>);
+        let backend = backend.unwrap_or_else(|| {
+            println!("No audio output, continuing without sound");
+            Box::new(backend_null::Null)
+        });
+        Audio {
+            backend,
+            volume: storage::read(VOLUME_SAVE_NAME).and_then(|volume|volume.trim().parse().ok()).unwrap_or(0.8),
+            music: None
+        }
+    }
+
+    /// Doesn't need an audio device, e.g. for headless simulations
+    pub fn null() -> Self {
+        Audio {
+            backend: Box::new(backend_null::Null),
+            volume: 0.8,
+            music: None
+        }
+    }
+
+    pub fn play(&mut self, sound: Sound) {
+        if self.volume > 0.0 {
+            self.backend.play(sound.data(), self.volume);
+        }
+    }
+
+    /// Starts a track from sounds/music unless it's already playing, None stops the music
+    pub fn set_music(&mut self, music: Option<&str>, volume: f32) {
+        let music = music.filter(|name|*name != "none");
+        if self.music.as_ref().map(|(name, volume)|(name.as_str(), *volume)) == music.map(|name|(name, volume)) {
+            return
+        }
+        self.backend.play_music(music.and_then(sounds::music), volume * self.volume);
+        self.music = music.map(|name|(name.to_string(), volume));
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Between 0 and 1, remembered for the next session
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.min(1.0).max(0.0); // clamp is still unstable
+        if let Some((_, music_volume)) = &self.music {
+            self.backend.set_music_volume(music_volume * self.volume);
+        }
+        storage::write(VOLUME_SAVE_NAME, &self.volume.to_string());
+    }
+}
+
+/// Whether levels can use the track
+pub fn music_exists(name: &str) -> bool {
+    (name == "none") | sounds::music(name).is_some()
+}
@@ -7,6 +7,7 @@ use crate::renderer::Layer;
 
 
 // TODO: merge bounds into pos
+#[derive(Clone)]
 pub struct Pos {
     pub curr: Vec2,
     /// Position during the previous update,
@@ -24,57 +25,219 @@ impl From<Vec2> for Pos {
     }
 }
 
+#[derive(Clone)]
 pub struct Collider {
     pub bounds: Bounds
 }
 
+#[derive(Clone)]
 pub struct Physics {
     // TODO: maybe smallvec?
     /// Bounds relative to position
     pub bounds: Bounds,
     pub vel: Vec2,
     pub gravity: bool,
-    pub collided: (Horizontal, Vertical)
+    pub collided: (Horizontal, Vertical),
+    /// Normal of the last surface hit during the update, pointing away from it
+    pub contact_normal: Option<Vec2>
 }
 
 // Carefull: Allways use both ChildOf and Children 
+#[derive(Clone)]
 pub struct ChildOf {
     pub parent: Entity,
     pub offset: Vec2,
     pub collision: Bounds // Neccessary because we can't borrow physics
 }
+#[derive(Clone)]
 pub struct Children(pub Vec<Entity>);
 
+#[derive(Clone)]
 pub struct Killable {
     pub bounds: Bounds,
+    /// Otherwise it is removed, and walkers don't need to reach the exit
     pub loss_on_death: bool
 }
 
+/// How many hazard hits a Killable survives
+#[derive(Clone)]
+pub struct Health {
+    pub hits: u32,
+    pub max_hits: u32,
+    /// Seconds left in which hazards don't hurt
+    pub invulnerable: f32
+}
+
+impl Health {
+    pub fn new(max_hits: u32) -> Self {
+        Health {
+            hits: max_hits,
+            max_hits,
+            invulnerable: 0.0
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Hazzard {
     pub bounds: Bounds
 }
 
+#[derive(Clone)]
 pub struct Carryable {
     pub detect_bounds: Bounds,
     pub carry_offset: Vec2,
     pub carried: bool
 }
 
+/// How a walker like Cary finds its way
+#[derive(Clone)]
 pub struct Walking {
-    pub max_speed: f32,
-    pub acc: f32,
-    pub jump_time_max: f32,
-    pub jump_time: f32,
+    pub speed: f32,
+    /// In tiles, higher walls make it turn around
+    pub jump_height: u32,
+    /// Upwards speed for jumping up one tile
+    pub jump_speed_low: f32,
+    /// Upwards speed for the higher jumps
+    pub jump_speed: f32,
+    /// In tiles, deeper drops make it turn around. None walks off any ledge.
+    pub max_drop: Option<u32>,
+    /// Wait in front of hazzards instead of walking into them
+    pub stop_at_hazzards: bool
 }
 
-pub struct RemoveOnImpact {}
+impl Default for Walking {
+    fn default() -> Self {
+        Walking {
+            speed: 1.8,
+            jump_height: 2,
+            jump_speed_low: 5.0,
+            jump_speed: 6.7,
+            max_drop: None,
+            stop_at_hazzards: false
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RemoveOnImpact {
+    /// Speed given to movable blocks that are hit, or to whoever carries them
+    pub knockback: f32
+}
+
+/// Can push Pushable bodies by walking into them
+#[derive(Clone)]
+pub struct Pusher {
+    /// Pushable bodies rest on these bounds.
+    /// Copy of the physics bounds, neccessary because we can't borrow physics.
+    pub bounds: Bounds
+}
+
+#[derive(Clone)]
+pub struct Pushable {}
+
+/// Moves along waypoints, e.g. platforms and sliding hazzards
+#[derive(Clone)]
+pub struct PathFollower {
+    pub waypoints: Vec<Vec2>,
+    /// Tiles per second
+    pub speed: f32,
+    /// Start over at the first waypoint instead of turning around at the last
+    pub looping: bool,
+    /// Index of the next waypoint
+    pub target: usize,
+    pub backwards: bool
+}
+
+impl PathFollower {
+    /// Starts at the first waypoint
+    pub fn new(waypoints: Vec<Vec2>, speed: f32, looping: bool) -> Self {
+        PathFollower {
+            waypoints,
+            speed,
+            looping,
+            target: 1,
+            backwards: false
+        }
+    }
+
+    /// Moves towards the next waypoints, returns the new position
+    pub fn step(&mut self, mut pos: Vec2, time: f32) -> Vec2 {
+        let mut distance = self.speed * time;
+        // Each waypoint at most twice per update, in case they are all in the same place
+        for _ in 0..self.waypoints.len() * 2 {
+            let to_target = self.waypoints[self.target] - pos;
+            let len = to_target.len();
+            if len > distance {
+                return pos + to_target * (distance / len)
+            }
+            pos = self.waypoints[self.target];
+            distance -= len;
+            self.advance();
+        }
+        pos
+    }
+
+    fn advance(&mut self) {
+        let last = self.waypoints.len() - 1;
+        if self.looping {
+            self.target = (self.target + 1) % self.waypoints.len();
+        } else if self.backwards {
+            if self.target == 0 {
+                self.backwards = false;
+                self.target = 1.min(last);
+            } else {
+                self.target -= 1;
+            }
+        } else if self.target == last {
+            self.backwards = true;
+            self.target = last.saturating_sub(1);
+        } else {
+            self.target += 1;
+        }
+    }
+}
 
+#[derive(Clone)]
 pub struct Shooter {
-    pub cooldown: f32
+    pub cooldown: f32,
+    pub max_cooldown: f32,
+    pub bullet_speed: f32,
+    /// Of the bullets, see RemoveOnImpact
+    pub knockback: f32,
+    /// Targets further away are ignored
+    pub range: f32,
+    pub target: ShooterTarget,
+    pub aim: Aim,
+    /// Shots left in the current burst
+    pub burst_left: u32
+}
+
+/// Who a shooter fires at, if it can see them
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShooterTarget {
+    Player,
+    /// Cary or the other walkers
+    Cary,
+    /// Whoever is closest
+    Nearest
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Aim {
+    /// At where the target is
+    Direct,
+    /// Always in the direction of the rotation, without waiting for a target
+    Fixed(u8),
+    /// At where the target will be when the bullet gets there
+    Lead,
+    /// Several shots in quick succession
+    Burst(u32)
 }
 
 // Not neccessary with way the game turned out
-pub struct Controllable { 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Controllable {
     pub horizontal: Horizontal,
     pub vertical: Vertical,
     pub pick_up: bool,
@@ -97,6 +260,7 @@ pub enum Vertical { None, Up, Down }
 
 
 // TODO: maybe smallvec?
+#[derive(Clone)]
 pub struct Sprite {
     pub offset: Vec2,
     pub tex_anchor: TexAnchor,
@@ -187,7 +351,7 @@ pub fn make_tile_solid(x: i32, y: i32) -> (Pos, Sprite, Collider) {
     )
 }
 
-pub fn make_tile_movable(x: i32, y: i32) -> (Pos, Sprite, Collider, Physics, Carryable) {
+pub fn make_tile_movable(x: i32, y: i32) -> (Pos, Sprite, Collider, Physics, Carryable, Pushable) {
     let bounds = Bounds::around(Vec2(0.0, 0.5), Vec2(0.95, 0.95));
     (
         Vec2(x as f32, y as f32).into(),
@@ -199,13 +363,29 @@ pub fn make_tile_movable(x: i32, y: i32) -> (Pos, Sprite, Collider, Physics, Car
             bounds,
             vel: Vec2::zero(),
             gravity: true,
-            collided: (Horizontal::None, Vertical::None)
+            collided: (Horizontal::None, Vertical::None),
+            contact_normal: None
         },
         Carryable {
             detect_bounds: Bounds::around(Vec2(0.0, 1.4), Vec2(1.2, 0.8)),
             carry_offset: Vec2(0.0, -(bounds.size().1 + 0.55/2.0)),
             carried: false
         },
+        Pushable {}
+    )
+}
+
+/// Usually moves along a path
+pub fn make_platform(x: i32, y: i32) -> (Pos, Sprite, Collider) {
+    (
+        Vec2(x as f32, y as f32).into(),
+        Sprite {
+            offset: Vec2(0.0, 0.5),
+            ..Sprite::single(PLATFORM, TexAnchor::Bottom, Layer::ForegroundTile, 0)
+        },
+        Collider {
+            bounds: Bounds::around(Vec2(0.0, 0.75), Vec2(1.0, 0.5))
+        }
     )
 }
 
@@ -245,24 +425,27 @@ pub fn make_trap(x: i32, y: i32, rotation: u8) -> (Pos, Hazzard, Sprite) {
     )
 }
 
-pub fn make_bullet(pos: Vec2, target: Vec2) -> (Pos, Sprite, Physics, Hazzard, RemoveOnImpact) {
+pub fn make_bullet(pos: Vec2, vel: Vec2, knockback: f32) -> (Pos, Sprite, Physics, Hazzard, RemoveOnImpact) {
     (
         pos.into(),
         Sprite::ani(BULLET, TexAnchor::Center, Layer::ForegroundTile, 0.3, true, 0),
         Physics {
             bounds: Bounds::around(Vec2::zero(), Vec2(0.6, 0.6)),
-            vel: (target-pos).norm() * 3.0,
+            vel,
             gravity: false,
-            collided: (Horizontal::None, Vertical::None)
+            collided: (Horizontal::None, Vertical::None),
+            contact_normal: None
         },
         Hazzard {
             bounds: Bounds::around(Vec2::zero(), Vec2(0.4, 0.4))
         },
-        RemoveOnImpact {}
+        RemoveOnImpact {
+            knockback
+        }
     )
 }
 
-pub fn make_shooter(x: i32, y: i32) -> (Pos, Sprite, Collider, Shooter) {
+pub fn make_shooter(x: i32, y: i32, shooter: Shooter) -> (Pos, Sprite, Collider, Shooter) {
     (
         Vec2(x as f32, y as f32).into(),
         Sprite {
@@ -271,7 +454,10 @@ pub fn make_shooter(x: i32, y: i32) -> (Pos, Sprite, Collider, Shooter) {
             tex_anchor: TexAnchor::Center,
             layer: Layer::ForegroundTile,
             mirror: false,
-            rotation: 0,
+            rotation: match shooter.aim {
+                Aim::Fixed(rotation) => rotation,
+                _ => 0
+            },
             frame_duration: f32::INFINITY,
             timer: 0.0,
             repeat: false,
@@ -280,19 +466,18 @@ pub fn make_shooter(x: i32, y: i32) -> (Pos, Sprite, Collider, Shooter) {
         Collider {
             bounds: Bounds::around(Vec2(0.0, 0.5), Vec2(1.0, 1.0))
         },
-        Shooter {
-            cooldown: 2.0
-        }
+        shooter
     )
 }
 
+#[derive(Clone)]
 pub struct Player {
     pub flap_cooldown: f32,
     pub carrying: Option<Entity>,
     pub stamina: f32 // max: 1.0
 }
 
-pub fn make_player(pos: Vec2) -> (Player, Pos, Physics, Controllable, Children, Killable, Sprite){
+pub fn make_player(pos: Vec2) -> (Player, Pos, Physics, Controllable, Children, Killable, Health, Sprite){
     let bounds = Bounds::around(Vec2::zero(), Vec2(0.55, 0.55));
     (
         Player { 
@@ -305,7 +490,8 @@ pub fn make_player(pos: Vec2) -> (Player, Pos, Physics, Controllable, Children,
             bounds,
             vel: Vec2::zero(),
             gravity: true,
-            collided: (Horizontal::None, Vertical::None)
+            collided: (Horizontal::None, Vertical::None),
+            contact_normal: None
         },
         Controllable::default(),
         Children(Vec::new()),
@@ -313,36 +499,46 @@ pub fn make_player(pos: Vec2) -> (Player, Pos, Physics, Controllable, Children,
             bounds,
             loss_on_death: true
         },
+        Health::new(1),
         Sprite::ani(PLAYER_FLY, TexAnchor::Center, Layer::Foreground, 0.08, false, 0),
     )
 }
 
+#[derive(Clone)]
 pub struct Cary {
     pub walk_right: bool
 }
 
-pub fn make_cary(pos: Vec2) -> (Cary, Pos, Physics, Killable, Carryable, Sprite) { 
+/// Also used for the other walkers, which look different
+pub fn make_cary(pos: Vec2, tex: &'static [TexCoords]) -> (Cary, Pos, Physics, Walking, Killable, Health, Carryable, Pusher, Sprite) { 
+    let bounds = Bounds::around(Vec2(0.0, 0.6), Vec2(0.7, 1.2));
     (
         Cary {
             walk_right: true
         },
         pos.into(),
         Physics {
-            bounds: Bounds::around(Vec2(0.0, 0.6), Vec2(0.7, 1.2)),
+            bounds,
             vel: Vec2::zero(),
             gravity: true,
-            collided: (Horizontal::None, Vertical::None)
+            collided: (Horizontal::None, Vertical::None),
+            contact_normal: None
         },
+        Walking::default(),
         Killable {
             bounds: Bounds::around(Vec2(0.0, 0.6), Vec2(0.3, 1.2)),
             loss_on_death: true
         },
+        Health::new(1),
         Carryable {
             detect_bounds: Bounds::around(Vec2(0.0, 1.5), Vec2(1.2, 1.0)),
             carry_offset: Vec2(0.0, -1.30),
             carried: false
         },
-        Sprite::ani(CARY_WALK, TexAnchor::Bottom, Layer::ForegroundPlayer, 0.2, true, 0)
+        Pusher {
+            bounds
+        },
+        Sprite::ani(tex, TexAnchor::Bottom, Layer::ForegroundPlayer, 0.2, true, 0)
     )
 }
 
@@ -354,4 +550,66 @@ pub fn make_exit(x: i32, y: i32) -> (Pos, Exit, Sprite) {
         Exit( Bounds::around(Vec2(0.0, 0.6), Vec2(0.3, 1.6))),
         Sprite::single(EXIT, TexAnchor::Bottom, Layer::Foreground, 0)
     )
-}
\ No newline at end of file
+}
+
+/// Retrying after a loss starts from the last checkpoint a walker passed
+pub struct Checkpoint(pub Bounds);
+
+pub fn make_checkpoint(x: i32, y: i32) -> (Pos, Checkpoint, Sprite) {
+    (
+        Vec2(x as f32, y as f32).into(),
+        Checkpoint(Bounds::around(Vec2(0.0, 0.6), Vec2(0.3, 1.6))),
+        Sprite::single(&CHECKPOINT[..1], TexAnchor::Bottom, Layer::Foreground, 0)
+    )
+}
+
+/// Sends its signal while something rests on it
+#[derive(Clone)]
+pub struct PressurePlate {
+    pub bounds: Bounds,
+    pub signal: u32,
+    pub pressed: bool
+}
+
+pub fn make_pressure_plate(x: i32, y: i32, signal: u32) -> (Pos, PressurePlate, Sprite) {
+    (
+        Vec2(x as f32, y as f32).into(),
+        PressurePlate {
+            bounds: Bounds::around(Vec2(0.0, 0.15), Vec2(0.8, 0.3)),
+            signal,
+            pressed: false
+        },
+        Sprite::single(&PLATE[..1], TexAnchor::Bottom, Layer::Foreground, 0)
+    )
+}
+
+/// Reacts to the pressure plates with the same signal
+#[derive(Clone)]
+pub struct Receiver {
+    pub signal: u32,
+    /// Active while the signal is off instead
+    pub inverted: bool,
+    /// Doors are open and spikes retracted while active
+    pub active: bool
+}
+
+/// Solid while closed, the bounds are those of its collider
+#[derive(Clone)]
+pub struct Door(pub Bounds);
+
+pub fn make_door(x: i32, y: i32, receiver: Receiver) -> (Pos, Door, Receiver, Collider, Sprite) {
+    let bounds = Bounds::around(Vec2(0.0, 0.5), Vec2(1.0, 1.0));
+    (
+        Vec2(x as f32, y as f32).into(),
+        Door(bounds),
+        receiver,
+        Collider {
+            bounds
+        },
+        Sprite::single(&DOOR[..1], TexAnchor::Bottom, Layer::ForegroundTile, 0)
+    )
+}
+
+/// Spikes that can be retracted, the bounds are those of their hazzard
+#[derive(Clone)]
+pub struct Retractable(pub Bounds);
@@ -0,0 +1,276 @@
+use std::collections::HashSet;
+use winit::{
+    event::{VirtualKeyCode, MouseButton, ElementState, KeyboardInput},
+    dpi::{PhysicalPosition, PhysicalSize},
+};
+use crate::{World, Camera, UI_CAMERA, TIME_BETWEEN_UPDATES};
+use crate::math::*;
+use crate::level;
+use crate::textures::{self, TexCoords, TexAnchor};
+use crate::renderer::{Renderer, Layer};
+use crate::text::{self, Style};
+
+/// Glyphs that can be placed, with the sprite shown under the cursor
+const BRUSHES: &[(char, &[TexCoords], u8)] = &[
+    ('#', textures::TILE_SOLID, 0),
+    ('M', textures::TILE_MOVEABLE, 1),
+    ('=', textures::PLATFORM, 0),
+    ('_', textures::PLATE, 0),
+    ('D', textures::DOOR, 0),
+    ('^', textures::SPIKES, 0),
+    ('>', textures::SPIKES, 1),
+    ('v', textures::SPIKES, 2),
+    ('<', textures::SPIKES, 3),
+    ('-', textures::DIVIDER, 1),
+    ('|', textures::DIVIDER, 0),
+    ('u', textures::TRAP_CEIL, 0),
+    ('⊂', textures::TRAP_CEIL, 1),
+    ('n', textures::TRAP_CEIL, 2),
+    ('⊃', textures::TRAP_CEIL, 3),
+    ('S', textures::SHOOTER, 0),
+    ('E', textures::EXIT, 0),
+    ('F', textures::CHECKPOINT, 0),
+    ('P', textures::PLAYER_IDLE, 0),
+    ('C', textures::CARY_WALK, 0),
+    ('c', textures::SIBLING_WALK, 0),
+    ('.', textures::RED, 0),
+];
+
+const NEW_LEVEL: &str = "\
+####################
+#                  #
+#                  #
+#                  #
+#                  #
+#                  #
+#                  #
+#                  #
+####################
+";
+
+/// Fills up lines when placing past their end
+const OUTSIDE: char = '.';
+const ERASED: char = ' ';
+
+/// Left click places the selected glyph, right click erases.
+/// Q/E or the mouse wheel select the glyph, the arrow keys move the view.
+/// Tab switches to play-testing (handled by main), F2 saves.
+pub struct Editor {
+    /// Everything up to and including the [tiles] line of a version 2 level
+    header: String,
+    /// Everything after the tiles of a version 2 level
+    footer: String,
+    lines: Vec<Vec<char>>,
+    /// Rebuilt after every change
+    preview: Option<World>,
+    /// Why the last change couldn't be previewed
+    preview_error: Option<String>,
+    path: String,
+    brush: usize,
+    camera: Camera,
+    /// Tile under the mouse cursor
+    cursor: (i32, i32),
+    /// Glyph being painted while a mouse button is held
+    painting: Option<char>,
+    pressed_keys: HashSet<VirtualKeyCode>
+}
+
+impl Editor {
+    pub fn new(level_string: &str, path: String) -> Self {
+        let mut header = String::new();
+        let mut tiles = Vec::new();
+        let mut footer = String::new();
+        if level_string.lines().next().map(str::trim) == Some("[level]") {
+            // Only edit the tiles, keep the rest as it is
+            let mut section = 0;
+            for line in level_string.lines() {
+                match section {
+                    0 => {
+                        header.push_str(line);
+                        header.push('\n');
+                        if line.trim() == "[tiles]" {
+                            section = 1;
+                        }
+                    },
+                    1 if line.trim().starts_with('[') => {
+                        section = 2;
+                        footer.push_str(line);
+                        footer.push('\n');
+                    },
+                    1 => tiles.push(line),
+                    _ => {
+                        footer.push_str(line);
+                        footer.push('\n');
+                    }
+                }
+            }
+        } else {
+            tiles.extend(level_string.lines());
+        }
+
+        let mut editor = Editor {
+            header,
+            footer,
+            lines: tiles.iter().map(|line|line.chars().collect()).collect(),
+            preview: None,
+            preview_error: None,
+            path,
+            brush: 0,
+            camera: Camera {
+                pos: Vec2(tiles.iter().map(|line|line.chars().count()).max().unwrap_or(0) as f32 / 2.0,
+                          -(tiles.len() as f32) / 2.0),
+                size: 10.0
+            },
+            cursor: (0, 0),
+            painting: None,
+            pressed_keys: HashSet::new()
+        };
+        editor.update_preview();
+        editor
+    }
+
+    /// Opens a level file, or starts a new level if it doesn't exist
+    pub fn open(path: String) -> Self {
+        let level_string = std::fs::read_to_string(&path).unwrap_or_else(|_|NEW_LEVEL.to_string());
+        Self::new(&level_string, path)
+    }
+
+    /// The level in the text format
+    pub fn level_string(&self) -> String {
+        let mut string = self.header.clone();
+        for line in &self.lines {
+            string.extend(line.iter());
+            string.push('\n');
+        }
+        string.push_str(&self.footer);
+        string
+    }
+
+    pub fn save(&self) {
+        match std::fs::write(&self.path, self.level_string()) {
+            Ok(()) => println!("Saved level to {}", self.path),
+            Err(err) => println!("Failed to save level to {}: {}", self.path, err)
+        }
+    }
+
+    pub fn input(&mut self, input: KeyboardInput) {
+        if let Some(keycode) = input.virtual_keycode {
+            if input.state == ElementState::Pressed {
+                match keycode {
+                    VirtualKeyCode::Q => self.select_brush(-1),
+                    VirtualKeyCode::E => self.select_brush(1),
+                    VirtualKeyCode::F2 => self.save(),
+                    _ => ()
+                }
+                self.pressed_keys.insert(keycode);
+            } else {
+                self.pressed_keys.remove(&keycode);
+            }
+        }
+    }
+
+    pub fn select_brush(&mut self, offset: i32) {
+        self.brush = (self.brush as i32 + offset).rem_euclid(BRUSHES.len() as i32) as usize;
+    }
+
+    pub fn mouse_input(&mut self, button: MouseButton, state: ElementState) {
+        self.painting = match (button, state) {
+            (MouseButton::Left, ElementState::Pressed) => Some(BRUSHES[self.brush].0),
+            (MouseButton::Right, ElementState::Pressed) => Some(ERASED),
+            _ => None
+        };
+        self.paint();
+    }
+
+    pub fn cursor_moved(&mut self, position: PhysicalPosition<f64>, window_size: PhysicalSize<u32>) {
+        let aspect_ratio = window_size.height as f32 / window_size.width as f32;
+        let screen_pos = Vec2(
+            2.0 * position.x as f32 / window_size.width as f32 - 1.0,
+            1.0 - 2.0 * position.y as f32 / window_size.height as f32
+        );
+        let pos = self.camera.pos + Vec2(screen_pos.0 / aspect_ratio, screen_pos.1) * self.camera.size;
+        // Tiles are positioned by their bottom center
+        self.cursor = (pos.0.round() as i32, pos.1.floor() as i32);
+        self.paint();
+    }
+
+    pub fn update(&mut self) {
+        let scroll_speed = 12.0;
+        let mut direction = Vec2::zero();
+        for keycode in &self.pressed_keys {
+            direction += match keycode {
+                VirtualKeyCode::Left => Vec2(-1.0, 0.0),
+                VirtualKeyCode::Right => Vec2(1.0, 0.0),
+                VirtualKeyCode::Up => Vec2(0.0, 1.0),
+                VirtualKeyCode::Down => Vec2(0.0, -1.0),
+                _ => Vec2::zero()
+            }
+        }
+        self.camera.pos += direction * scroll_speed * TIME_BETWEEN_UPDATES;
+    }
+
+    fn paint(&mut self) {
+        let glyph = match self.painting {
+            Some(glyph) => glyph,
+            None => return
+        };
+        let (x, y) = self.cursor;
+        if (x < 0) | (y > 0) {
+            return
+        }
+        let (column, line) = (x as usize, -y as usize);
+        if self.lines.get(line).and_then(|line|line.get(column)) == Some(&glyph) {
+            return
+        }
+
+        // There can only be one player
+        if glyph == 'P' {
+            for tile in self.lines.iter_mut().flat_map(|line|line.iter_mut()) {
+                if *tile == glyph {
+                    *tile = ERASED;
+                }
+            }
+        }
+
+        if self.lines.len() <= line {
+            self.lines.resize(line + 1, Vec::new());
+        }
+        let tiles = &mut self.lines[line];
+        if tiles.len() <= column {
+            tiles.resize(column + 1, OUTSIDE);
+        }
+        tiles[column] = glyph;
+        self.update_preview();
+    }
+
+    fn update_preview(&mut self) {
+        match level::preview(&self.level_string()) {
+            Ok(world) => {
+                self.preview = Some(world);
+                self.preview_error = None;
+            },
+            Err(err) => {
+                println!("Failed to preview level - {}", err);
+                self.preview_error = Some(err.to_string());
+            }
+        }
+    }
+
+    pub fn render(&self, renderer: &mut Renderer) {
+        if let Some(preview) = &self.preview {
+            preview.render_sprites(renderer, &self.camera, 1.0);
+        }
+
+        let (_, tex, rotation) = BRUSHES[self.brush];
+        renderer.draw(&self.camera, Vec2(self.cursor.0 as f32, self.cursor.1 as f32), TexAnchor::Bottom,
+            &tex[0], Layer::UI, false, rotation);
+
+        if let Some(err) = &self.preview_error {
+            let style = Style { color: (0.8, 0.0, 0.0), max_width: Some(16.0), ..Style::default() };
+            text::draw(renderer, &UI_CAMERA, Vec2(-8.0, -5.0), err, &style);
+        }
+
+        renderer.set_transition(&self.camera, Vec2::zero(), 0.0, false);
+        renderer.render();
+    }
+}
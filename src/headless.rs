@@ -0,0 +1,131 @@
+use crate::{World, WorldState, Vec2, TIME_BETWEEN_UPDATES};
+use crate::level::{Levels, LevelError};
+use crate::components::*;
+use crate::audio::{Audio, Sound};
+
+/// Runs a level without a window or renderer, with scripted input
+/// instead of the keyboard. Intended for automated tests.
+pub struct Simulation {
+    world: World,
+    ticks: u32,
+    /// Null backend, so the same code path plays sounds as in the game
+    audio: Audio,
+    /// Every sound played so far
+    sounds: Vec<Sound>
+}
+
+impl Simulation {
+    pub fn new(level_string: &str) -> Result<Self, LevelError> {
+        Ok(Simulation {
+            world: Levels::new(vec![level_string.to_string()]).load()?,
+            ticks: 0,
+            audio: Audio::null(),
+            sounds: Vec::new()
+        })
+    }
+
+    /// Advances the world by one update (TIME_BETWEEN_UPDATES seconds).
+    /// The input is ignored once the level has been won or lost.
+    pub fn step(&mut self, control: Controllable) -> WorldState {
+        self.world.update(control);
+        self.ticks += 1;
+        for sound in self.world.sounds.drain(..) {
+            self.audio.play(sound);
+            self.sounds.push(sound);
+        }
+        self.world.state
+    }
+
+    /// Steps with one input per update until the level is won or lost.
+    /// If the inputs run out before that, no input is given until
+    /// max_ticks updates have passed in total.
+    pub fn run<I: IntoIterator<Item=Controllable>>(&mut self, inputs: I, max_ticks: u32) -> WorldState {
+        let mut inputs = inputs.into_iter();
+        while let WorldState::Running = self.world.state {
+            if self.ticks >= max_ticks {
+                break
+            }
+            self.step(inputs.next().unwrap_or_default());
+        }
+        self.world.state
+    }
+
+    pub fn state(&self) -> WorldState {
+        self.world.state
+    }
+
+    /// Number of updates so far
+    pub fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    /// Simulated time in seconds
+    pub fn time(&self) -> f32 {
+        self.ticks as f32 * TIME_BETWEEN_UPDATES
+    }
+
+    pub fn player_pos(&self) -> Vec2 {
+        self.world.entities.get::<Pos>(self.world.player).unwrap().curr
+    }
+
+    pub fn sounds(&self) -> &[Sound] {
+        &self.sounds
+    }
+
+    /// Positions of Cary and the other walkers that haven't reached an exit yet
+    pub fn walker_positions(&self) -> Vec<Vec2> {
+        self.world.query::<(&Pos, &Cary)>().iter().map(|(_, (pos, _))|pos.curr).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Replay;
+
+    fn control(horizontal: Horizontal, vertical: Vertical, pick_up: bool) -> Controllable {
+        Controllable { horizontal, vertical, pick_up }
+    }
+
+    #[test]
+    fn default_levels_run() {
+        for (index, level_string) in Levels::default().level_strings.iter().enumerate() {
+            let mut simulation = Simulation::new(level_string)
+                .unwrap_or_else(|err|panic!("Level {} failed to load: {}", index, err));
+            // Flies back and forth, picking things up and dropping them
+            let inputs = (0..).map(|tick| control(
+                if tick % 50 < 25 { Horizontal::Left } else { Horizontal::Right },
+                if tick % 4 == 0 { Vertical::Up } else { Vertical::None },
+                tick % 60 == 0
+            ));
+            simulation.run(inputs, 1000);
+        }
+    }
+
+    #[test]
+    fn scripted_win() {
+        use Horizontal::{Left, Right};
+        use Vertical::Up;
+        // Carries Cary over the wall of the first level
+        let runs = [
+            (control(Left, Vertical::None, false), 48),
+            (control(Left, Vertical::None, true), 1),
+            (control(Horizontal::None, Up, false), 28),
+            (control(Right, Up, false), 2),
+            (control(Right, Vertical::None, false), 38),
+            (control(Right, Up, false), 2),
+            (control(Right, Vertical::None, false), 11),
+            (control(Right, Up, false), 1),
+            (control(Right, Vertical::None, false), 24),
+            (control(Right, Up, false), 1),
+            (control(Right, Vertical::None, false), 30)
+        ];
+        let replay = Replay {
+            level_string: Levels::default().level_strings[0].clone(),
+            inputs: runs.iter().flat_map(|&(control, count)|(0..count).map(move |_|control)).collect()
+        };
+        assert!(matches!(replay.simulate(), Ok(WorldState::Victory(..))));
+        let decoded = Replay::decode(&replay.encode()).unwrap();
+        assert!(matches!(decoded.simulate(), Ok(WorldState::Victory(..))));
+    }
+}
@@ -0,0 +1,476 @@
+use std::collections::{HashMap, HashSet};
+use winit::event::{VirtualKeyCode, ElementState, KeyboardInput};
+use crate::components::{Controllable, Horizontal, Vertical};
+use crate::storage;
+
+/// Sticks have to be pushed further than this to count
+const STICK_DEADZONE: f32 = 0.3;
+/// File name on native, localStorage key on the web
+const CONFIG_NAME: &str = "cary_config";
+
+/// What the player wants to do, independent of the device
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Flap,
+    Dive,
+    PickUp,
+    /// Menus and continuing after the end of a level
+    Confirm,
+    Pause,
+    /// Held to scrub back through the last few seconds of play
+    Rewind
+}
+
+pub const ACTIONS: &[Action] = &[
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::Flap,
+    Action::Dive,
+    Action::PickUp,
+    Action::Confirm,
+    Action::Pause,
+    Action::Rewind
+];
+
+impl Action {
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Flap => "Flap",
+            Action::Dive => "Dive",
+            Action::PickUp => "Pick up",
+            Action::Confirm => "Confirm",
+            Action::Pause => "Pause",
+            Action::Rewind => "Rewind"
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ActionEvent {
+    pub action: Action,
+    /// Actions are released once nothing triggering them is held anymore
+    pub pressed: bool
+}
+
+/// Gamepad buttons in the standard layout
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Button {
+    South,
+    East,
+    West,
+    North,
+    Start,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight
+}
+
+/// A key on the keyboard, independent of the layout except for the defaults
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    /// Only physical on native, on the web it's the layout dependent key code
+    Scancode(u32),
+    /// The web derives it from the physical key, native from the layout
+    Virtual(u32)
+}
+
+impl Key {
+    fn matches(self, input: KeyboardInput) -> bool {
+        match self {
+            Key::Scancode(scancode) => scancode == input.scancode,
+            Key::Virtual(keycode) => Some(keycode) == input.virtual_keycode.map(|keycode|keycode as u32)
+        }
+    }
+
+    /// The physical key of the input
+    fn of(input: KeyboardInput) -> Option<Key> {
+        if cfg!(target_arch="wasm32") {
+            input.virtual_keycode.map(|keycode|Key::Virtual(keycode as u32))
+        } else {
+            Some(Key::Scancode(input.scancode))
+        }
+    }
+}
+
+struct Binding {
+    key: Key,
+    /// Shown in menus, so players see what's written on their keys.
+    /// Scancodes don't say that, so on native it's updated whenever the key is pressed.
+    name: String,
+    action: Action
+}
+
+/// Letters by position on native, these scancodes work on Linux and Windows
+#[cfg(not(any(target_arch="wasm32", target_os="macos")))]
+const LETTER_KEYS: [Key; 6] = [Key::Scancode(30), Key::Scancode(32), Key::Scancode(17), Key::Scancode(31), Key::Scancode(36), Key::Scancode(19)];
+#[cfg(target_os="macos")]
+const LETTER_KEYS: [Key; 6] = [Key::Scancode(0), Key::Scancode(2), Key::Scancode(13), Key::Scancode(1), Key::Scancode(38), Key::Scancode(15)];
+#[cfg(target_arch="wasm32")]
+const LETTER_KEYS: [Key; 6] = [
+    Key::Virtual(VirtualKeyCode::A as u32),
+    Key::Virtual(VirtualKeyCode::D as u32),
+    Key::Virtual(VirtualKeyCode::W as u32),
+    Key::Virtual(VirtualKeyCode::S as u32),
+    Key::Virtual(VirtualKeyCode::J as u32),
+    Key::Virtual(VirtualKeyCode::R as u32)
+];
+
+/// The letters could be anything on other layouts, so they're described by their position until pressed
+fn default_bindings() -> Vec<Binding> {
+    let [a, d, w, s, j, r] = LETTER_KEYS;
+    let virtual_key = |keycode: VirtualKeyCode|Key::Virtual(keycode as u32);
+    let bindings = vec![
+        (a, "QWERTY A", Action::MoveLeft),
+        (virtual_key(VirtualKeyCode::Left), "Left", Action::MoveLeft),
+        (d, "QWERTY D", Action::MoveRight),
+        (virtual_key(VirtualKeyCode::Right), "Right", Action::MoveRight),
+        (w, "QWERTY W", Action::Flap),
+        (virtual_key(VirtualKeyCode::Up), "Up", Action::Flap),
+        (s, "QWERTY S", Action::Dive),
+        (virtual_key(VirtualKeyCode::Down), "Down", Action::Dive),
+        (j, "QWERTY J", Action::PickUp),
+        (j, "QWERTY J", Action::Confirm),
+        (virtual_key(VirtualKeyCode::Return), "Return", Action::Confirm),
+        (virtual_key(VirtualKeyCode::Escape), "Escape", Action::Pause),
+        (r, "QWERTY R", Action::Rewind)
+    ];
+    bindings.into_iter().map(|(key, name, action)|Binding { key, name: name.to_string(), action }).collect()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Source {
+    Key(Key),
+    /// Gamepad id and button
+    Button(usize, Button),
+    /// Gamepad id and the action of the stick direction
+    Stick(usize, Action)
+}
+
+enum GamepadEvent {
+    Button(usize, Button, bool),
+    /// Left stick, up and right are positive
+    StickX(usize, f32),
+    StickY(usize, f32),
+    Disconnected(usize)
+}
+
+/// Turns keyboard and gamepad input into actions
+pub struct Input {
+    bindings: Vec<Binding>,
+    /// Keys, buttons and stick directions being held, with their actions
+    held: HashMap<Source, Vec<Action>>,
+    /// Actions pressed since the last call to control()
+    fresh: HashSet<Action>,
+    gamepads: Gamepads
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Input {
+            bindings: load_bindings(),
+            held: HashMap::new(),
+            fresh: HashSet::new(),
+            gamepads: Gamepads::new()
+        }
+    }
+
+    pub fn keyboard(&mut self, input: KeyboardInput) -> Vec<ActionEvent> {
+        let mut events = Vec::new();
+        if let Some(key) = Key::of(input) {
+            if let (Key::Scancode(_), Some(_)) = (key, input.virtual_keycode) {
+                let name = key_name(input);
+                for binding in self.bindings.iter_mut().filter(|binding|binding.key == key) {
+                    binding.name.clone_from(&name);
+                }
+            }
+            let actions = self.bindings.iter()
+                .filter(|binding|binding.key.matches(input))
+                .map(|binding|binding.action)
+                .collect();
+            self.set(Source::Key(key), actions, input.state == ElementState::Pressed, &mut events);
+        }
+        events
+    }
+
+    /// Replaces the keys of the action with the key of the input and saves the bindings.
+    /// Returns false without binding for keys that are already held, e.g. repeats of the one that started rebinding.
+    pub fn bind(&mut self, action: Action, input: KeyboardInput) -> bool {
+        let key = match Key::of(input) {
+            Some(key) if !self.held.contains_key(&Source::Key(key)) => key,
+            _ => return false
+        };
+        self.bindings.retain(|binding|binding.action != action);
+        self.bindings.push(Binding { key, name: key_name(input), action });
+        save_bindings(&self.bindings);
+        // Held without actions, so its repeats and release don't trigger anything
+        self.held.insert(Source::Key(key), Vec::new());
+        true
+    }
+
+    /// Names of the keys bound to the action
+    pub fn key_names(&self, action: Action) -> Vec<&str> {
+        self.bindings.iter()
+            .filter(|binding|binding.action == action)
+            .map(|binding|binding.name.as_str())
+            .collect()
+    }
+
+    /// Has to be called regularly, gamepads don't generate window events
+    pub fn poll_gamepads(&mut self) -> Vec<ActionEvent> {
+        let mut events = Vec::new();
+        for event in self.gamepads.poll() {
+            match event {
+                GamepadEvent::Button(id, button, pressed)
+                    => self.set(Source::Button(id, button), button_actions(button).to_vec(), pressed, &mut events),
+                GamepadEvent::StickX(id, value) => {
+                    self.set(Source::Stick(id, Action::MoveLeft), vec![Action::MoveLeft], value < -STICK_DEADZONE, &mut events);
+                    self.set(Source::Stick(id, Action::MoveRight), vec![Action::MoveRight], value > STICK_DEADZONE, &mut events);
+                },
+                GamepadEvent::StickY(id, value) => {
+                    self.set(Source::Stick(id, Action::Flap), vec![Action::Flap], value > STICK_DEADZONE, &mut events);
+                    self.set(Source::Stick(id, Action::Dive), vec![Action::Dive], value < -STICK_DEADZONE, &mut events);
+                },
+                GamepadEvent::Disconnected(id) => {
+                    let sources: Vec<_> = self.held.keys()
+                        .filter(|source|matches!(source, Source::Button(source_id, _) | Source::Stick(source_id, _) if *source_id == id))
+                        .copied()
+                        .collect();
+                    for source in sources {
+                        self.set(source, Vec::new(), false, &mut events);
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    fn set(&mut self, source: Source, actions: Vec<Action>, pressed: bool, events: &mut Vec<ActionEvent>) {
+        if pressed {
+            // Ignore key repeats and unchanged gamepad state
+            if !self.held.contains_key(&source) {
+                for &action in &actions {
+                    self.fresh.insert(action);
+                    events.push(ActionEvent { action, pressed });
+                }
+                self.held.insert(source, actions);
+            }
+        } else if let Some(actions) = self.held.remove(&source) {
+            for action in actions {
+                if !self.is_held(action) {
+                    events.push(ActionEvent { action, pressed });
+                }
+            }
+        }
+    }
+
+    pub fn is_held(&self, action: Action) -> bool {
+        self.held.values().any(|actions|actions.contains(&action))
+    }
+
+    /// Player input for the next update
+    pub fn control(&mut self) -> Controllable {
+        // None: Not held, Some(true): Pressed since the last update, Some(false): Held for longer
+        let state = |action| if self.is_held(action) { Some(self.fresh.contains(&action)) } else { None };
+        let control = Controllable {
+            vertical: match (state(Action::Flap), state(Action::Dive)) {
+                (Some(true), Some(true)) => Vertical::None,
+                (Some(false), Some(false)) => Vertical::None,
+                (Some(_), _) => Vertical::Up,
+                (_, Some(_)) => Vertical::Down,
+                (None, None) => Vertical::None
+            },
+            horizontal: match (state(Action::MoveLeft), state(Action::MoveRight)) {
+                (Some(true), Some(true)) => Horizontal::None,
+                (Some(false), Some(false)) => Horizontal::None,
+                (Some(_), _) => Horizontal::Left,
+                (_, Some(_)) => Horizontal::Right,
+                (None, None) => Horizontal::None
+            },
+            pick_up: state(Action::PickUp) == Some(true)
+        };
+        self.fresh.clear();
+        control
+    }
+}
+
+/// What the layout has on the key
+fn key_name(input: KeyboardInput) -> String {
+    match input.virtual_keycode {
+        Some(keycode) => format!("{:?}", keycode),
+        None => format!("#{}", input.scancode)
+    }
+}
+
+/// Format: one "<action> <s for scancode or v for virtual keycode><code> <name>" per line.
+/// Actions missing from the config keep their default keys.
+fn load_bindings() -> Vec<Binding> {
+    let config = match storage::read(CONFIG_NAME) {
+        Some(config) => config,
+        None => return default_bindings()
+    };
+    let mut bindings = Vec::new();
+    for line in config.lines() {
+        let fields: Vec<_> = line.splitn(3, ' ').collect();
+        if let [action, key, name] = fields.as_slice() {
+            let action = ACTIONS.iter().find(|known|format!("{:?}", known) == *action);
+            let key = match (key.get(..1), key.get(1..).and_then(|code|code.parse().ok())) {
+                (Some("s"), Some(code)) => Some(Key::Scancode(code)),
+                (Some("v"), Some(code)) => Some(Key::Virtual(code)),
+                _ => None
+            };
+            if let (Some(&action), Some(key)) = (action, key) {
+                bindings.push(Binding { key, name: name.to_string(), action });
+            }
+        }
+    }
+    for binding in default_bindings() {
+        if bindings.iter().all(|configured|configured.action != binding.action) {
+            bindings.push(binding);
+        }
+    }
+    bindings
+}
+
+fn save_bindings(bindings: &[Binding]) {
+    let mut string = String::new();
+    for binding in bindings {
+        let key = match binding.key {
+            Key::Scancode(code) => format!("s{}", code),
+            Key::Virtual(code) => format!("v{}", code)
+        };
+        string.push_str(&format!("{:?} {} {}\n", binding.action, key, binding.name));
+    }
+    storage::write(CONFIG_NAME, &string);
+}
+
+fn button_actions(button: Button) -> &'static [Action] {
+    match button {
+        Button::South => &[Action::Flap, Action::Confirm],
+        Button::East => &[Action::Dive],
+        Button::West => &[Action::PickUp],
+        Button::North => &[Action::Rewind],
+        Button::Start => &[Action::Pause],
+        Button::DPadUp => &[Action::Flap],
+        Button::DPadDown => &[Action::Dive],
+        Button::DPadLeft => &[Action::MoveLeft],
+        Button::DPadRight => &[Action::MoveRight]
+    }
+}
+
+#[cfg(not(target_arch="wasm32"))]
+struct Gamepads(Option<gilrs::Gilrs>);
+
+#[cfg(not(target_arch="wasm32"))]
+impl Gamepads {
+    fn new() -> Self {
+        match gilrs::Gilrs::new() {
+            Ok(gilrs) => Gamepads(Some(gilrs)),
+            Err(err) => {
+                println!("Failed to initialize gamepads: {}", err);
+                Gamepads(None)
+            }
+        }
+    }
+
+    fn poll(&mut self) -> Vec<GamepadEvent> {
+        use gilrs::{EventType, Axis};
+        let mut events = Vec::new();
+        let gilrs = match &mut self.0 {
+            Some(gilrs) => gilrs,
+            None => return events
+        };
+        while let Some(gilrs::Event { id, event, .. }) = gilrs.next_event() {
+            let id: usize = id.into();
+            events.push(match event {
+                EventType::ButtonPressed(button, _) | EventType::ButtonReleased(button, _) => {
+                    let button = match button {
+                        gilrs::Button::South => Button::South,
+                        gilrs::Button::East => Button::East,
+                        gilrs::Button::West => Button::West,
+                        gilrs::Button::North => Button::North,
+                        gilrs::Button::Start => Button::Start,
+                        gilrs::Button::DPadUp => Button::DPadUp,
+                        gilrs::Button::DPadDown => Button::DPadDown,
+                        gilrs::Button::DPadLeft => Button::DPadLeft,
+                        gilrs::Button::DPadRight => Button::DPadRight,
+                        _ => continue
+                    };
+                    GamepadEvent::Button(id, button, matches!(event, EventType::ButtonPressed(..)))
+                },
+                EventType::AxisChanged(Axis::LeftStickX, value, _) => GamepadEvent::StickX(id, value),
+                EventType::AxisChanged(Axis::LeftStickY, value, _) => GamepadEvent::StickY(id, value),
+                EventType::Disconnected => GamepadEvent::Disconnected(id),
+                _ => continue
+            });
+        }
+        events
+    }
+}
+
+/// Uses the Gamepad API, which has to be polled for the current state
+#[cfg(target_arch="wasm32")]
+struct Gamepads {
+    connected: HashSet<usize>
+}
+
+#[cfg(target_arch="wasm32")]
+impl Gamepads {
+    fn new() -> Self {
+        Gamepads {
+            connected: HashSet::new()
+        }
+    }
+
+    fn poll(&mut self) -> Vec<GamepadEvent> {
+        use wasm_bindgen::JsCast;
+        // Button indices of the standard mapping
+        const BUTTONS: &[(u32, Button)] = &[
+            (0, Button::South),
+            (1, Button::East),
+            (2, Button::West),
+            (3, Button::North),
+            (9, Button::Start),
+            (12, Button::DPadUp),
+            (13, Button::DPadDown),
+            (14, Button::DPadLeft),
+            (15, Button::DPadRight)
+        ];
+
+        let mut events = Vec::new();
+        let gamepads = match web_sys::window().and_then(|window|window.navigator().get_gamepads().ok()) {
+            Some(gamepads) => gamepads,
+            None => return events
+        };
+        let mut connected = HashSet::new();
+        for index in 0..gamepads.length() {
+            // Empty slots are null
+            let gamepad = match gamepads.get(index).dyn_into::<web_sys::Gamepad>() {
+                Ok(gamepad) if gamepad.connected() => gamepad,
+                _ => continue
+            };
+            let id = gamepad.index() as usize;
+            connected.insert(id);
+
+            let buttons = gamepad.buttons();
+            for &(index, button) in BUTTONS {
+                let pressed = buttons.get(index).dyn_into::<web_sys::GamepadButton>()
+                    .map_or(false, |button|button.pressed());
+                events.push(GamepadEvent::Button(id, button, pressed));
+            }
+            let axes = gamepad.axes();
+            events.push(GamepadEvent::StickX(id, axes.get(0).as_f64().unwrap_or(0.0) as f32));
+            // Down is positive in the Gamepad API
+            events.push(GamepadEvent::StickY(id, -axes.get(1).as_f64().unwrap_or(0.0) as f32));
+        }
+        for &id in self.connected.difference(&connected) {
+            events.push(GamepadEvent::Disconnected(id));
+        }
+        self.connected = connected;
+        events
+    }
+}
@@ -1,6 +1,11 @@
+use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::time::SystemTime;
+use hecs::Entity;
 use crate::World;
 use crate::math::*;
 use crate::components::*;
+use crate::textures::{CARY_WALK, SIBLING_WALK};
 
 const DEFAULT_LEVELS: &[&str] = &[
     include_str!("../levels/level_0"),
@@ -11,9 +16,40 @@ const DEFAULT_LEVELS: &[&str] = &[
     include_str!("../levels/level_5"),
 ];
 
+/// Information from the header of a version 2 level
+#[derive(Default, Clone, Debug)]
+pub struct LevelMeta {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    /// In seconds
+    pub par_time: Option<f32>,
+    /// Name of a track in sounds/music, "none" for silence
+    pub music: Option<String>,
+    /// Relative to the player's volume setting
+    pub music_volume: Option<f32>,
+    /// The camera center is kept within these
+    pub camera_bounds: Option<Bounds>,
+    /// Header entries the game itself doesn't use
+    pub properties: HashMap<String, String>
+}
+
+/// Parameters from the [entities] section, keyed by tile position
+type EntityParams = HashMap<(i32, i32), TileParams>;
+
+struct TileParams {
+    /// Index of the line they were defined in
+    line: usize,
+    values: HashMap<String, String>,
+    /// Keys spawn() asked for, any others are mistakes
+    read: RefCell<HashSet<String>>
+}
+
 pub struct Levels {
     level: usize,
-    pub level_strings: Vec<String>
+    /// Changed through set(), which keeps the names up to date
+    pub level_strings: Vec<String>,
+    /// From the headers, so the level select doesn't parse them each frame
+    names: Vec<Option<String>>
 }
 
 
@@ -25,10 +61,17 @@ impl Levels {
     pub fn new(level_strings: Vec<String>) -> Self {
         Levels {
             level: 0,
+            names: level_strings.iter().map(|level_string|meta(level_string).and_then(|meta|meta.name)).collect(),
             level_strings
         }
     }
 
+    /// Replaces a level, e.g. after its file changed
+    pub fn set(&mut self, index: usize, level_string: String) {
+        self.names[index] = meta(&level_string).and_then(|meta|meta.name);
+        self.level_strings[index] = level_string;
+    }
+
     pub fn next(&mut self) -> bool {
         if self.level + 1 < self.level_strings.len() {
             self.level += 1;
@@ -38,95 +81,724 @@ impl Levels {
         }
     }
 
-    pub fn load(&self) -> World {
-        let level_string = &self.level_strings[self.level];
-
-        let mut world = World::new();
-    
-        let mut x = 0;
-        let mut y = 0;
-        // TODO: spawn_batched() would be faster
-        for c in level_string.chars() {
-            let mut background = true;
-            match c {
-                'P' => {
-                    let mut player_pos = world.entities.get_mut::<Pos>(world.player).unwrap();
-                    player_pos.curr = Vec2(x as f32, y as f32);
-                },
-                'C' => {
-                    let mut cary_pos = world.entities.get_mut::<Pos>(world.cary).unwrap();
-                    cary_pos.curr = Vec2(x as f32, y as f32);
-                },
-                'E' => {
-                    world.entities.spawn(make_exit(x, y));
-                },
-                '#' => {
-                    background = false;
-                    world.entities.spawn(make_tile_solid(x, y));
-                },
-                ' ' => (),
-                'M' => {
-                    world.entities.spawn(make_tile_movable(x, y));
-                },
-                '^' => {
-                    world.entities.spawn(make_spikes(x, y, 0));
-                },
-                '>' => {
-                    world.entities.spawn(make_spikes(x, y, 1));
-                },
-                'v' => {
-                    world.entities.spawn(make_spikes(x, y, 2));
-                },
-                '<' => {
-                    world.entities.spawn(make_spikes(x, y, 3));
-                },
-                '-' => {
-                    world.entities.spawn(make_divider(x, y, false));
-                },
-                '|' => {
-                    world.entities.spawn(make_divider(x, y, true));
-                },
-                'u' => {
-                    world.entities.spawn(make_trap(x, y, 0));
-                },
-                '⊂' => {
-                    world.entities.spawn(make_trap(x, y, 1));
-                },
-                'n' => {
-                    world.entities.spawn(make_trap(x, y, 2));
-                },
-                '⊃' => {
-                    world.entities.spawn(make_trap(x, y, 3));
-                },
-                'S' => {
-                    background = false;
-                    world.entities.spawn(make_shooter(x, y));
-                },
-                '.' => {
-                    background = false;
-                },
-                '\n' => {
-                    background = false;
-                    y -= 1;
-                    x = -1;
-                },
-                '\r' => {
-                    background = false;
-                },
-                _ => {
-                    println!("Failed to load level - Unknown entity: {}", c);
-                    std::process::exit(2);
+    /// Makes the level with the given index the current one, if it exists
+    pub fn select(&mut self, index: usize) {
+        if index < self.level_strings.len() {
+            self.level = index;
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.level
+    }
+
+    pub fn current(&self) -> &str {
+        &self.level_strings[self.level]
+    }
+
+    /// Of the current level, if its header has one
+    pub fn name(&self) -> Option<&str> {
+        self.names[self.level].as_deref()
+    }
+
+    pub fn load(&self) -> Result<World, LevelError> {
+        load(self.current())
+    }
+}
+
+/// Notices changes to level files by polling their modification time
+pub struct LevelWatcher {
+    paths: Vec<String>,
+    modified: Vec<Option<SystemTime>>
+}
+
+impl LevelWatcher {
+    pub fn new(paths: Vec<String>) -> Self {
+        let modified = paths.iter().map(|path|modified(path)).collect();
+        LevelWatcher {
+            paths,
+            modified
+        }
+    }
+
+    /// Level indices and new contents of the files changed since the last call
+    pub fn changed(&mut self) -> Vec<(usize, String)> {
+        let mut changed = Vec::new();
+        for (index, (path, last_modified)) in self.paths.iter().zip(self.modified.iter_mut()).enumerate() {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                // The file may be gone briefly while an editor saves it
+                if let Ok(string) = std::fs::read_to_string(path) {
+                    changed.push((index, string));
                 }
             }
-            if background {
-                world.entities.spawn(make_tile_background(x, y));
+        }
+        changed
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata|metadata.modified()).ok()
+}
+
+/// Builds the world of a level and checks that it is playable
+pub fn load(level_string: &str) -> Result<World, LevelError> {
+    let (mut world, spawned) = spawn(level_string)?;
+    let tiles = &spawned.tiles;
+
+    let no_position = |reason| LevelError { position: None, glyph: None, reason };
+    match spawned.players.as_slice() {
+        [] => return Err(no_position(LevelErrorReason::NoPlayer)),
+        [_] => (),
+        [_, (x, y), ..] => return Err(tiles.error(*x, *y, 'P', LevelErrorReason::MultiplePlayers))
+    }
+    if spawned.walkers.is_empty() {
+        return Err(no_position(LevelErrorReason::NoCary))
+    }
+    if spawned.exits == 0 {
+        return Err(no_position(LevelErrorReason::NoExit))
+    }
+
+    for (entity, x, y, c) in spawned.to_check {
+        let bounds = world.entities.get::<Physics>(entity).unwrap().bounds
+            + world.entities.get::<Pos>(entity).unwrap().curr;
+        for collider_entity in world.colliders.query(bounds) {
+            let collider_bounds = world.entities.get::<Collider>(collider_entity).unwrap().bounds
+                + world.entities.get::<Pos>(collider_entity).unwrap().curr;
+            if (collider_entity != entity) & bounds.overlapps(collider_bounds) {
+                return Err(tiles.error(x, y, c, LevelErrorReason::OverlapsSolid))
+            }
+        }
+    }
+
+    let (x, y) = spawned.walkers[0];
+    world.camera.pos = Vec2(x as f32, y as f32);
+
+    Ok(world)
+}
+
+/// Builds the world of a level without checking whether it is playable.
+/// If there's no player, it is despawned, so the world must not be updated.
+pub fn preview(level_string: &str) -> Result<World, LevelError> {
+    let (mut world, spawned) = spawn(level_string)?;
+    if spawned.players.is_empty() {
+        world.despawn(world.player);
+    }
+    Ok(world)
+}
+
+/// Only the header, e.g. to show the name without loading the level
+pub fn meta(level_string: &str) -> Option<LevelMeta> {
+    parse(level_string).ok().map(|(meta, _, _)|meta)
+}
+
+/// What was found while spawning the tiles
+struct Spawned {
+    tiles: Tiles,
+    players: Vec<(i32, i32)>,
+    /// Cary and the other NPCs to escort
+    walkers: Vec<(i32, i32)>,
+    exits: usize,
+    /// Entities which must not start inside a collider, with their glyph
+    to_check: Vec<(Entity, i32, i32, char)>
+}
+
+fn spawn(level_string: &str) -> Result<(World, Spawned), LevelError> {
+    let (meta, tiles, params) = parse(level_string)?;
+
+    let mut world = World::new();
+    world.meta = meta;
+
+    let mut players = Vec::new();
+    let mut walkers = Vec::new();
+    let mut exits = 0;
+    let mut to_check = Vec::new();
+    // Glyphs of the tiles which have an entity that can take parameters
+    let mut occupied = HashMap::new();
+
+    let mut x = 0;
+    let mut y = 0;
+    // TODO: spawn_batched() would be faster
+    for c in tiles.text.chars() {
+        let mut background = true;
+        // Static entities, these can follow paths
+        let entity = match c {
+            'P' => {
+                players.push((x, y));
+                let mut player_pos = world.entities.get_mut::<Pos>(world.player).unwrap();
+                player_pos.curr = Vec2(x as f32, y as f32);
+                drop(player_pos);
+                *world.entities.get_mut::<Health>(world.player).unwrap() = Health::new(hits(&params, x, y)?);
+                to_check.push((world.player, x, y, c));
+                None
+            },
+            'C' | 'c' => {
+                walkers.push((x, y));
+                let (tex, default_walking) = match c {
+                    'C' => (CARY_WALK, Walking::default()),
+                    // Cary's little sibling is slower and can't jump as high
+                    _ => (SIBLING_WALK, Walking { speed: 1.4, jump_height: 1, ..Walking::default() })
+                };
+                let walker = world.entities.spawn(make_cary(Vec2(x as f32, y as f32), tex));
+                *world.entities.get_mut::<Health>(walker).unwrap() = Health::new(hits(&params, x, y)?);
+                *world.entities.get_mut::<Walking>(walker).unwrap() = walking(&params, x, y, default_walking)?;
+                // Walkers that aren't needed at the exit, e.g. loss_on_death=false
+                world.entities.get_mut::<Killable>(walker).unwrap().loss_on_death = param(&params, x, y, "loss_on_death")?.unwrap_or(true);
+                to_check.push((walker, x, y, c));
+                None
+            },
+            'E' => {
+                exits += 1;
+                Some(world.entities.spawn(make_exit(x, y)))
+            },
+            'F' => {
+                Some(world.entities.spawn(make_checkpoint(x, y)))
+            },
+            '#' => {
+                background = false;
+                Some(world.entities.spawn(make_tile_solid(x, y)))
+            },
+            ' ' => None,
+            'M' => {
+                to_check.push((world.entities.spawn(make_tile_movable(x, y)), x, y, c));
+                None
+            },
+            '=' => Some(world.entities.spawn(make_platform(x, y))),
+            '_' => {
+                Some(world.entities.spawn(make_pressure_plate(x, y, param(&params, x, y, "signal")?.unwrap_or(0))))
+            },
+            'D' => {
+                Some(world.entities.spawn(make_door(x, y, receiver(&params, x, y)?)))
+            },
+            '^' => {
+                Some(world.entities.spawn(make_spikes(x, y, rotation(&params, x, y, 0)?)))
+            },
+            '>' => {
+                Some(world.entities.spawn(make_spikes(x, y, rotation(&params, x, y, 1)?)))
+            },
+            'v' => {
+                Some(world.entities.spawn(make_spikes(x, y, rotation(&params, x, y, 2)?)))
+            },
+            '<' => {
+                Some(world.entities.spawn(make_spikes(x, y, rotation(&params, x, y, 3)?)))
+            },
+            '-' => {
+                Some(world.entities.spawn(make_divider(x, y, false)))
+            },
+            '|' => {
+                Some(world.entities.spawn(make_divider(x, y, true)))
+            },
+            'u' => {
+                Some(world.entities.spawn(make_trap(x, y, rotation(&params, x, y, 0)?)))
+            },
+            '⊂' => {
+                Some(world.entities.spawn(make_trap(x, y, rotation(&params, x, y, 1)?)))
+            },
+            'n' => {
+                Some(world.entities.spawn(make_trap(x, y, rotation(&params, x, y, 2)?)))
+            },
+            '⊃' => {
+                Some(world.entities.spawn(make_trap(x, y, rotation(&params, x, y, 3)?)))
+            },
+            'S' => {
+                background = false;
+                Some(world.entities.spawn(make_shooter(x, y, shooter(&params, x, y)?)))
+            },
+            '.' => {
+                background = false;
+                None
+            },
+            '\n' => {
+                background = false;
+                y -= 1;
+                x = -1;
+                None
+            },
+            '\r' => {
+                background = false;
+                None
+            },
+            _ => return Err(tiles.error(x, y, c, LevelErrorReason::UnknownGlyph))
+        };
+        if let (Some(entity), Some(path)) = (entity, path(&params, x, y)?) {
+            world.entities.insert_one(entity, path).unwrap();
+        }
+        // Spikes with a signal can be retracted
+        if let Some(entity) = entity {
+            if matches!(c, '^' | '>' | 'v' | '<') & param::<u32>(&params, x, y, "signal")?.is_some() {
+                let bounds = world.entities.get::<Hazzard>(entity).unwrap().bounds;
+                world.entities.insert(entity, (Retractable(bounds), receiver(&params, x, y)?)).unwrap();
+            }
+        }
+        if entity.is_some() | matches!(c, 'P' | 'C' | 'c') {
+            occupied.insert((x, y), c);
+        }
+        if background {
+            world.entities.spawn(make_tile_background(x, y));
+        }
+        x += 1;
+    }
+    check_params(&params, &occupied)?;
+    world.index_all();
+
+    Ok((world, Spawned {
+        tiles,
+        players,
+        walkers,
+        exits,
+        to_check
+    }))
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelError {
+    /// Line and column in the level string, counting from 1
+    pub position: Option<(usize, usize)>,
+    pub glyph: Option<char>,
+    pub reason: LevelErrorReason
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LevelErrorReason {
+    UnknownGlyph,
+    NoPlayer,
+    MultiplePlayers,
+    NoCary,
+    NoExit,
+    OverlapsSolid,
+    /// Malformed header or entity parameters
+    Syntax(String)
+}
+
+impl std::fmt::Display for LevelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((line, column)) = self.position {
+            write!(f, "{}:{}: ", line, column)?;
+        }
+        match &self.reason {
+            LevelErrorReason::UnknownGlyph => write!(f, "Unknown entity")?,
+            LevelErrorReason::NoPlayer => write!(f, "No player (P)")?,
+            LevelErrorReason::MultiplePlayers => write!(f, "More than one player (P)")?,
+            LevelErrorReason::NoCary => write!(f, "No Cary (C)")?,
+            LevelErrorReason::NoExit => write!(f, "No exit (E)")?,
+            LevelErrorReason::OverlapsSolid => write!(f, "Overlaps a solid tile")?,
+            LevelErrorReason::Syntax(message) => write!(f, "{}", message)?
+        }
+        if let Some(glyph) = self.glyph {
+            write!(f, ": {}", glyph)?;
+        }
+        Ok(())
+    }
+}
+
+/// The [tiles] section
+struct Tiles {
+    text: String,
+    /// Index of the first tile line in the level string
+    first_line: usize
+}
+
+impl Tiles {
+    fn error(&self, x: i32, y: i32, glyph: char, reason: LevelErrorReason) -> LevelError {
+        LevelError {
+            position: Some((self.first_line + (-y) as usize + 1, x as usize + 1)),
+            glyph: Some(glyph),
+            reason
+        }
+    }
+}
+
+/// Splits a level into header, tiles and entity parameters.
+/// 
+/// Version 1 levels only consist of tiles. Version 2 levels look like this:
+/// ```text
+/// [level]
+/// version = 2
+/// name = Some name
+/// author = Someone
+/// par_time = 30
+/// music = some_track
+/// music_volume = 0.5
+/// camera = <min column> <min line> <max column> <max line>
+/// anything_else = goes into properties
+/// [tiles]
+/// <tiles as in version 1>
+/// [entities]
+/// <column> <line> key=value other_key="value with spaces"
+/// ```
+/// Columns and lines of the tiles are counted from 0 at the top left.
+/// Spawning rejects parameters the entity at that tile doesn't use.
+fn parse(level_string: &str) -> Result<(LevelMeta, Tiles, EntityParams), LevelError> {
+    let mut lines = level_string.lines().enumerate();
+    if lines.next().map(|(_, line)|line.trim()) != Some("[level]") {
+        return Ok((LevelMeta::default(), Tiles { text: level_string.to_string(), first_line: 0 }, HashMap::new()))
+    }
+
+    enum Section { Level, Tiles, Entities }
+    let mut section = Section::Level;
+    let mut meta = LevelMeta::default();
+    let mut version = None;
+    let mut tiles = Tiles { text: String::new(), first_line: 0 };
+    let mut params = EntityParams::new();
+
+    for (index, line) in lines {
+        match line.trim() {
+            "[level]" => { section = Section::Level; continue },
+            "[tiles]" => { section = Section::Tiles; tiles.first_line = index + 1; continue },
+            "[entities]" => { section = Section::Entities; continue },
+            _ => ()
+        }
+        match section {
+            Section::Level => {
+                if line.trim().is_empty() {
+                    continue
+                }
+                let (key, value) = match line.find('=') {
+                    Some(split) => (line[..split].trim(), line[split+1..].trim()),
+                    None => return Err(syntax_error(index, format!("Expected key = value in header: {}", line)))
+                };
+                match key {
+                    "version" => version = Some((index, parse_value::<u32>(index, key, value)?)),
+                    "name" => meta.name = Some(value.to_string()),
+                    "author" => meta.author = Some(value.to_string()),
+                    "par_time" => meta.par_time = Some(parse_value(index, key, value)?),
+                    "music" if crate::audio::music_exists(value) => meta.music = Some(value.to_string()),
+                    "music" => return Err(syntax_error(index, format!("Unknown music {}", value))),
+                    "music_volume" => meta.music_volume = Some(parse_value(index, key, value)?),
+                    "camera" => {
+                        let coords = value.split_whitespace()
+                            .map(|coord|parse_value::<i32>(index, key, coord))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        if coords.len() != 4 {
+                            return Err(syntax_error(index, "Expected four coordinates for camera".into()))
+                        }
+                        // Lines count downwards, y upwards
+                        meta.camera_bounds = Some(Bounds {
+                            min: Vec2(coords[0].min(coords[2]) as f32, -coords[1].max(coords[3]) as f32),
+                            max: Vec2(coords[0].max(coords[2]) as f32, -coords[1].min(coords[3]) as f32)
+                        });
+                    },
+                    _ => { meta.properties.insert(key.to_string(), value.to_string()); }
+                }
+            },
+            Section::Tiles => {
+                tiles.text.push_str(line);
+                tiles.text.push('\n');
+            },
+            Section::Entities => {
+                let tokens = tokenize(line);
+                if tokens.is_empty() {
+                    continue
+                }
+                if tokens.len() < 2 {
+                    return Err(syntax_error(index, format!("Expected column and line: {}", line)))
+                }
+                let x = parse_value::<i32>(index, "column", &tokens[0])?;
+                let y = -parse_value::<i32>(index, "line", &tokens[1])?;
+                let entry = params.entry((x, y)).or_insert_with(|| TileParams { line: index, values: HashMap::new(), read: RefCell::default() });
+                for token in &tokens[2..] {
+                    match token.find('=') {
+                        Some(split) => { entry.values.insert(token[..split].to_string(), token[split+1..].to_string()); },
+                        None => return Err(syntax_error(index, format!("Expected key=value: {}", token)))
+                    }
+                }
             }
-            x += 1;
         }
+    }
+
+    match version {
+        Some((_, 2)) => (),
+        Some((index, version)) => return Err(syntax_error(index, format!("Unsupported level version {}", version))),
+        None => return Err(syntax_error(0, "Missing level version".into()))
+    }
+
+    Ok((meta, tiles, params))
+}
+
+/// Parameters only take effect if the entity asks for them, so everything else is most likely a typo
+fn check_params(params: &EntityParams, occupied: &HashMap<(i32, i32), char>) -> Result<(), LevelError> {
+    let mut positions: Vec<_> = params.iter().collect();
+    positions.sort_by_key(|(_, tile_params)|tile_params.line);
+    for (&(x, y), tile_params) in positions {
+        let glyph = match occupied.get(&(x, y)) {
+            Some(&glyph) => glyph,
+            None => return Err(syntax_error(tile_params.line, format!("No entity at column {} line {}", x, -y)))
+        };
+        let read = tile_params.read.borrow();
+        let mut unread: Vec<_> = tile_params.values.keys().filter(|key|!read.contains(*key)).collect();
+        unread.sort();
+        if let Some(key) = unread.first() {
+            return Err(syntax_error(tile_params.line, format!("Unknown parameter for {}: {}", glyph, key)))
+        }
+    }
+    Ok(())
+}
+
+/// Splits at whitespace, except within double quotes
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted ^= true,
+            c if c.is_whitespace() & !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            },
+            c => token.push(c)
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
 
-        let cary_pos = world.entities.get_mut::<Pos>(world.cary).unwrap().curr;
-        world.camera.pos = cary_pos;
+fn param<T: std::str::FromStr>(params: &EntityParams, x: i32, y: i32, key: &str) -> Result<Option<T>, LevelError> {
+    match params.get(&(x, y)) {
+        Some(tile_params) => match tile_params.values.get(key) {
+            Some(value) => {
+                tile_params.read.borrow_mut().insert(key.to_string());
+                parse_value(tile_params.line, key, value).map(Some)
+            },
+            None => Ok(None)
+        },
+        None => Ok(None)
+    }
+}
 
-        world
+fn rotation(params: &EntityParams, x: i32, y: i32, default: u8) -> Result<u8, LevelError> {
+    let rotation = param(params, x, y, "rotation")?.unwrap_or(default);
+    if rotation > 3 {
+        return Err(syntax_error(params[&(x, y)].line, format!("Rotation must be between 0 and 3, got {}", rotation)))
     }
-}
\ No newline at end of file
+    Ok(rotation)
+}
+
+/// Waypoints after the tile itself, as path="<column> <line> ..."
+fn path(params: &EntityParams, x: i32, y: i32) -> Result<Option<PathFollower>, LevelError> {
+    let waypoints: String = match param(params, x, y, "path")? {
+        Some(waypoints) => waypoints,
+        None => return Ok(None)
+    };
+    let line = params[&(x, y)].line;
+    let coords = waypoints.split_whitespace()
+        .map(|coord|parse_value::<i32>(line, "path", coord))
+        .collect::<Result<Vec<_>, _>>()?;
+    if coords.is_empty() | (coords.len() % 2 != 0) {
+        return Err(syntax_error(line, "Expected pairs of column and line for path".into()))
+    }
+    let mut points = vec![Vec2(x as f32, y as f32)];
+    // Lines count downwards, y upwards
+    points.extend(coords.chunks(2).map(|coord|Vec2(coord[0] as f32, -coord[1] as f32)));
+    let looping = match param::<String>(params, x, y, "mode")?.as_deref() {
+        None | Some("pingpong") => false,
+        Some("loop") => true,
+        Some(mode) => return Err(syntax_error(line, format!("Unknown path mode {}, expected pingpong or loop", mode)))
+    };
+    let speed: f32 = param(params, x, y, "speed")?.unwrap_or(1.0);
+    if (speed <= 0.0) | !speed.is_finite() {
+        return Err(syntax_error(line, format!("Path speed must be above 0, got {}", speed)))
+    }
+    Ok(Some(PathFollower::new(points, speed, looping)))
+}
+
+/// Turrets can be tuned, e.g. target=nearest aim=lead bullet_speed=5
+fn shooter(params: &EntityParams, x: i32, y: i32) -> Result<Shooter, LevelError> {
+    let target = match param::<String>(params, x, y, "target")?.as_deref() {
+        None | Some("player") => ShooterTarget::Player,
+        Some("cary") => ShooterTarget::Cary,
+        Some("nearest") => ShooterTarget::Nearest,
+        Some(target) => return Err(syntax_error(params[&(x, y)].line, 
+            format!("Unknown target {}, expected player, cary or nearest", target)))
+    };
+    let aim = match param::<String>(params, x, y, "aim")?.as_deref() {
+        None | Some("direct") => Aim::Direct,
+        Some("fixed") => Aim::Fixed(rotation(params, x, y, 0)?),
+        Some("lead") => Aim::Lead,
+        Some("burst") => match param(params, x, y, "burst")?.unwrap_or(3) {
+            0 => return Err(syntax_error(params[&(x, y)].line, "A burst needs at least 1 shot".into())),
+            shots => Aim::Burst(shots)
+        },
+        Some(aim) => return Err(syntax_error(params[&(x, y)].line, 
+            format!("Unknown aim {}, expected direct, fixed, lead or burst", aim)))
+    };
+    Ok(Shooter {
+        cooldown: param(params, x, y, "delay")?.unwrap_or(2.0),
+        max_cooldown: param(params, x, y, "cooldown")?.unwrap_or(3.0),
+        bullet_speed: param(params, x, y, "bullet_speed")?.unwrap_or(3.0),
+        knockback: param(params, x, y, "knockback")?.unwrap_or(0.0),
+        range: param(params, x, y, "range")?.unwrap_or(f32::INFINITY),
+        target,
+        aim,
+        burst_left: 0
+    })
+}
+
+/// Doors and retractable spikes react to the pressure plates with the same signal
+fn receiver(params: &EntityParams, x: i32, y: i32) -> Result<Receiver, LevelError> {
+    Ok(Receiver {
+        signal: param(params, x, y, "signal")?.unwrap_or(0),
+        inverted: param(params, x, y, "inverted")?.unwrap_or(false),
+        active: false
+    })
+}
+
+/// How many hazard hits an entity survives, e.g. more for the player on easier levels
+fn hits(params: &EntityParams, x: i32, y: i32) -> Result<u32, LevelError> {
+    let hits = param(params, x, y, "hits")?.unwrap_or(1);
+    if hits == 0 {
+        return Err(syntax_error(params[&(x, y)].line, "Hits must be at least 1".into()))
+    }
+    Ok(hits)
+}
+
+/// Lets levels use variants of Cary
+fn walking(params: &EntityParams, x: i32, y: i32, default: Walking) -> Result<Walking, LevelError> {
+    Ok(Walking {
+        speed: walking_speed(params, x, y, "speed", default.speed)?,
+        jump_height: param(params, x, y, "jump_height")?.unwrap_or(default.jump_height),
+        jump_speed_low: walking_speed(params, x, y, "jump_speed_low", default.jump_speed_low)?,
+        jump_speed: walking_speed(params, x, y, "jump_speed", default.jump_speed)?,
+        max_drop: param(params, x, y, "max_drop")?.or(default.max_drop),
+        stop_at_hazzards: param(params, x, y, "stop_at_hazzards")?.unwrap_or(default.stop_at_hazzards)
+    })
+}
+
+/// Can be 0 to stand still or not jump, but negative or infinite speeds would break the walker
+fn walking_speed(params: &EntityParams, x: i32, y: i32, key: &str, default: f32) -> Result<f32, LevelError> {
+    let speed = param(params, x, y, key)?.unwrap_or(default);
+    if !speed.is_finite() | (speed < 0.0) {
+        return Err(syntax_error(params[&(x, y)].line, format!("{} must be finite and not negative, got {}", key, speed)))
+    }
+    Ok(speed)
+}
+
+fn parse_value<T: std::str::FromStr>(line: usize, key: &str, value: &str) -> Result<T, LevelError> {
+    value.parse().map_err(|_|syntax_error(line, format!("Invalid value for {}: {}", key, value)))
+}
+
+fn syntax_error(line: usize, message: String) -> LevelError {
+    LevelError {
+        position: Some((line + 1, 1)),
+        glyph: None,
+        reason: LevelErrorReason::Syntax(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_err(level_string: &str) -> String {
+        match load(level_string) {
+            Ok(_) => panic!("Loaded invalid level {:?}", level_string),
+            Err(err) => err.to_string()
+        }
+    }
+
+    fn v2(tiles: &str, entities: &str) -> String {
+        format!("[level]\nversion = 2\n[tiles]\n{}[entities]\n{}", tiles, entities)
+    }
+
+    #[test]
+    fn version_1() {
+        let (meta, tiles, params) = parse("#P C E#\n").ok().unwrap();
+        assert!(meta.name.is_none());
+        assert_eq!(tiles.text, "#P C E#\n");
+        assert_eq!(tiles.first_line, 0);
+        assert!(params.is_empty());
+        assert!(load("#P C E#\n").is_ok());
+    }
+
+    #[test]
+    fn version_2() {
+        let level_string = "[level]\nversion = 2\nname = Some name\nauthor = Someone\npar_time = 12.5\n\
+            camera = 0 3 10 1\nfoo = bar baz\n[tiles]\n#P C E#\n[entities]\n3 0 hits=3 hint=\"hello world\"\n";
+        let (meta, tiles, params) = parse(level_string).ok().unwrap();
+        assert_eq!(meta.name.as_deref(), Some("Some name"));
+        assert_eq!(meta.author.as_deref(), Some("Someone"));
+        assert_eq!(meta.par_time, Some(12.5));
+        let camera = meta.camera_bounds.unwrap();
+        assert_eq!((camera.min.0, camera.min.1, camera.max.0, camera.max.1), (0.0, -3.0, 10.0, -1.0));
+        assert_eq!(meta.properties.get("foo").map(String::as_str), Some("bar baz"));
+        assert_eq!(tiles.text, "#P C E#\n");
+        assert_eq!(tiles.first_line, 8);
+        assert_eq!(params[&(3, 0)].line, 10);
+        assert_eq!(params[&(3, 0)].values.get("hint").map(String::as_str), Some("hello world"));
+        assert_eq!(load_err(level_string), "11:1: Unknown parameter for C: hint");
+        assert!(load(&level_string.replace(" hint=\"hello world\"", "")).is_ok());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(load_err("[level]\n[tiles]\nP C E\n"), "1:1: Missing level version");
+        assert_eq!(load_err("[level]\nversion = 3\n[tiles]\nP C E\n"), "2:1: Unsupported level version 3");
+        assert_eq!(load_err("[level]\nversion = 2\nname\n"), "3:1: Expected key = value in header: name");
+        assert_eq!(load_err("[level]\nversion = 2\npar_time = soon\n"), "3:1: Invalid value for par_time: soon");
+        assert_eq!(load_err("[level]\nversion = 2\ncamera = 1 2 3\n"), "3:1: Expected four coordinates for camera");
+        assert_eq!(load_err(&v2("P C E\n", "0\n")), "6:1: Expected column and line: 0");
+        assert_eq!(load_err(&v2("P C E\n", "0 0 hits\n")), "6:1: Expected key=value: hits");
+    }
+
+    #[test]
+    fn tile_errors() {
+        assert_eq!(load_err("#P C E x#\n"), "1:8: Unknown entity: x");
+        // Positions count from the start of the level string, not of the tiles
+        assert_eq!(load_err(&v2("#P C E#\n#  x   #\n", "")), "5:4: Unknown entity: x");
+        assert_eq!(load_err("#P C E#\n#  P   #\n"), "2:4: More than one player (P): P");
+    }
+
+    #[test]
+    fn validation_errors() {
+        assert_eq!(load_err("# C E#\n"), "No player (P)");
+        assert_eq!(load_err("#P E#\n"), "No Cary (C)");
+        assert_eq!(load_err("#P C#\n"), "No exit (E)");
+        assert_eq!(load_err("#P  #E#\n#   C #\n"), "2:5: Overlaps a solid tile: C");
+        assert_eq!(load_err(&v2("#P C E#\n", "1 0 hits=0\n")), "6:1: Hits must be at least 1");
+        assert_eq!(load_err(&v2("#P C E#\n", "3 0 hits=0\n")), "6:1: Hits must be at least 1");
+    }
+
+    #[test]
+    fn param_errors() {
+        assert_eq!(load_err(&v2("#P C E#\n", "3 0 speeed=2\n")), "6:1: Unknown parameter for C: speeed");
+        assert_eq!(load_err(&v2("#P C^E#\n", "4 0 rotaton=1\n")), "6:1: Unknown parameter for ^: rotaton");
+        // Only read along with a path
+        assert_eq!(load_err(&v2("#P C E#\n#   = #\n", "4 1 speed=2\n")), "7:1: Unknown parameter for =: speed");
+        assert_eq!(load_err(&v2("#P C E#\n", "2 0 hits=2\n")), "6:1: No entity at column 2 line 0");
+        assert_eq!(load_err(&v2("#P C E#\n", "3 0 hits=2\n20 5 hits=2\n")), "7:1: No entity at column 20 line 5");
+        assert!(load(&v2("#P C E#\n", "1 0 hits=2\n3 0 hits=2 speed=1\n")).is_ok());
+    }
+
+    #[test]
+    fn loss_on_death() {
+        let world = load(&v2("#P CcE#\n", "4 0 loss_on_death=false\n")).ok().unwrap();
+        let mut flags: Vec<_> = world.query::<(&Pos, &Killable, &Cary)>().iter()
+            .map(|(_, (pos, killable, _))|(pos.curr.0 as i32, killable.loss_on_death))
+            .collect();
+        flags.sort();
+        assert_eq!(flags, vec![(3, true), (4, false)]);
+        assert_eq!(load_err(&v2("#P C E#\n", "3 0 loss_on_death=maybe\n")), "6:1: Invalid value for loss_on_death: maybe");
+    }
+
+    #[test]
+    fn walking_errors() {
+        assert!(load(&v2("#P C E#\n", "3 0 speed=0 jump_speed=8.5\n")).is_ok());
+        assert_eq!(load_err(&v2("#P C E#\n", "3 0 speed=-1\n")), "6:1: speed must be finite and not negative, got -1");
+        assert_eq!(load_err(&v2("#P C E#\n", "3 0 jump_speed=inf\n")), "6:1: jump_speed must be finite and not negative, got inf");
+        assert_eq!(load_err(&v2("#P C E#\n", "3 0 jump_speed_low=NaN\n")), "6:1: jump_speed_low must be finite and not negative, got NaN");
+        assert_eq!(load_err(&v2("#P C E#\n", "3 0 jump_height=-1\n")), "6:1: Invalid value for jump_height: -1");
+    }
+
+    #[test]
+    fn path_errors() {
+        assert!(load(&v2("#P C E#\n#   = #\n", "4 1 path=\"2 1\" speed=0.5\n")).is_ok());
+        assert_eq!(load_err(&v2("#P C E#\n#   = #\n", "4 1 path=\"2 1\" speed=0\n")), "7:1: Path speed must be above 0, got 0");
+        assert_eq!(load_err(&v2("#P C E#\n#   = #\n", "4 1 path=\"2 1\" speed=-2\n")), "7:1: Path speed must be above 0, got -2");
+        assert_eq!(load_err(&v2("#P C E#\n#   = #\n", "4 1 path=\"2 1\" speed=NaN\n")), "7:1: Path speed must be above 0, got NaN");
+        assert_eq!(load_err(&v2("#P C E#\n#   = #\n", "4 1 path=\"2\"\n")), "7:1: Expected pairs of column and line for path");
+    }
+}
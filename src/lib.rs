@@ -3,10 +3,20 @@ mod math;
 mod components;
 mod renderer;
 mod level;
+mod headless;
+mod replay;
+mod editor;
+mod progress;
+mod input;
+mod storage;
+mod spatial;
+mod rewind;
+mod particles;
+mod audio;
+mod text;
 
-use std::collections::HashMap;
 use winit::{
-    event::{Event, WindowEvent, VirtualKeyCode, },
+    event::{Event, WindowEvent, VirtualKeyCode, MouseScrollDelta, },
     event_loop::{ControlFlow, EventLoop},
 };
 #[cfg(target_arch="wasm32")]
@@ -14,8 +24,24 @@ use wasm_bindgen::prelude::wasm_bindgen;
 use hecs::Entity;
 use math::*;
 use components::*;
-use level::Levels;
-use renderer::{Renderer, Layer};
+use level::{Levels, LevelMeta, LevelWatcher};
+use editor::Editor;
+use progress::Progress;
+use spatial::Grid;
+use rewind::Rewind;
+use particles::Particles;
+use audio::Audio;
+use text::{Align, Style};
+use input::{Input, Action, ActionEvent, ACTIONS};
+
+pub use level::{LevelError, LevelErrorReason};
+use renderer::{Renderer, Layer, WHITE};
+
+pub use math::Vec2;
+pub use components::{Controllable, Horizontal, Vertical};
+pub use headless::Simulation;
+pub use replay::Replay;
+pub use audio::Sound;
 
 
 
@@ -23,6 +49,8 @@ const TIME_BETWEEN_UPDATES: f32 = 1.0 / 25.0;
 const MIN_TIME_BETWEEN_FRAMES: f32 = 1.0 / 60.0;
 
 const GAME_END_WAIT_TIME: f32 = 1.5;
+/// How often to check level files for changes
+const TIME_BETWEEN_LEVEL_WATCHES: f32 = 0.5;
 const UI_CAMERA: Camera = Camera {
     pos: Vec2(0.0, 0.0),
     size: 7.0
@@ -30,10 +58,48 @@ const UI_CAMERA: Camera = Camera {
 
 enum GameState {
     ShowControls,
+    /// The selected level is the current one of Levels
+    LevelSelect,
     WorldLoaded(World),
+    /// Index of the selected option in PAUSE_OPTIONS
+    Paused(World, usize),
+    /// Plays back recorded inputs instead of reading the keyboard
+    Replay(World, Replay),
+    /// Rebinding keys, returns to the previous state afterwards
+    Controls {
+        previous: Box<GameState>,
+        /// Index in ACTIONS
+        selected: usize,
+        /// Waiting for the key to bind to the selected action
+        rebinding: bool
+    },
+    /// The current level failed to load, with the reason
+    LevelError(String),
+    Editor(Editor),
+    /// Playing the level from the editor
+    PlayTest(World, Editor),
     Victory
 }
 
+#[derive(Copy, Clone)]
+enum PauseOption {
+    Resume,
+    Restart,
+    Controls,
+    LevelSelect,
+    Volume,
+    Quit
+}
+
+const PAUSE_OPTIONS: &[PauseOption] = &[
+    PauseOption::Resume,
+    PauseOption::Restart,
+    PauseOption::Controls,
+    PauseOption::LevelSelect,
+    PauseOption::Volume,
+    PauseOption::Quit
+];
+
 #[cfg(target_arch="wasm32")]
 #[wasm_bindgen(start)]
 pub fn wasm_main() {
@@ -54,9 +120,55 @@ pub fn main() {
         std::process::exit(0);
     }
 
-    let mut levels = if args.len() > 1 {
+    let mut level_paths = Vec::new();
+    let mut replay_path = None;
+    let mut record_path = None;
+    let mut edit_path = None;
+    let mut keep_position = false;
+    let mut args_iter = args.iter().skip(1);
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--replay" | "--record" | "--edit" => {
+                let path = match args_iter.next() {
+                    Some(path) => path,
+                    None => {
+                        println!("Missing file after {}", arg);
+                        std::process::exit(1);
+                    }
+                };
+                match arg.as_str() {
+                    "--replay" => replay_path = Some(path),
+                    "--record" => record_path = Some(path.clone()),
+                    _ => edit_path = Some(path.clone())
+                }
+            },
+            "--keep-position" => keep_position = true,
+            _ => level_paths.push(arg)
+        }
+    }
+
+    let replay = replay_path.map(|path| {
+        match std::fs::read(path).map_err(|err|err.to_string()).and_then(|bytes|Replay::decode(&bytes)) {
+            Ok(replay) => replay,
+            Err(err) => {
+                println!("Failed to read replay file {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    });
+
+    // Reload levels from the command line when they are changed
+    let mut level_watcher = if replay.is_none() & !level_paths.is_empty() {
+        Some(LevelWatcher::new(level_paths.iter().map(|path|path.to_string()).collect()))
+    } else {
+        None
+    };
+
+    let mut levels = if let Some(replay) = &replay {
+        Levels::new(vec![replay.level_string.clone()])
+    } else if !level_paths.is_empty() {
         let mut level_strings = Vec::new();
-        for path in args.iter().skip(1) {
+        for path in level_paths {
             level_strings.push(
                 match std::fs::read_to_string(path) {
                     Ok(string) => string,
@@ -85,12 +197,24 @@ pub fn main() {
     }
     let mut renderer = Renderer::create(&window);
 
-    let mut game_state = GameState::ShowControls;
+    let mut progress = Progress::load();
+    let mut input = Input::new();
+    let mut audio = Audio::new();
+
+    let mut game_state = match (replay, edit_path) {
+        (Some(replay), _) => match levels.load() {
+            Ok(world) => GameState::Replay(world, replay),
+            Err(err) => GameState::LevelError(err.to_string())
+        },
+        (None, Some(path)) => GameState::Editor(Editor::open(path)),
+        (None, None) => GameState::ShowControls
+    };
 
     // std::time's not available in wasm?
     // Also, maybe explicit requestAnimationFrame would be usefull on the web
     let mut last_update = Time::now();
     let mut last_frame = Time::now();
+    let mut last_level_watch = Time::now();
 
     event_loop.run(move |event, _, control_flow| {
         // Immediately restart loop; WaitUntil would suspend the thread
@@ -100,52 +224,135 @@ pub fn main() {
                 => renderer.resize(size.width, size.height),
             Event::WindowEvent { event: WindowEvent::CloseRequested, ..}
                 => *control_flow = ControlFlow::Exit,
-            Event::WindowEvent { event: WindowEvent::KeyboardInput { input, .. }, .. }
+            Event::WindowEvent { event: WindowEvent::CursorMoved { position, .. }, .. }
+                => if let GameState::Editor(editor) = &mut game_state {
+                    editor.cursor_moved(position, window.inner_size())
+                },
+            Event::WindowEvent { event: WindowEvent::MouseInput { state, button, .. }, .. }
+                => if let GameState::Editor(editor) = &mut game_state {
+                    editor.mouse_input(button, state)
+                },
+            Event::WindowEvent { event: WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(_, lines), .. }, .. }
+                => if let GameState::Editor(editor) = &mut game_state {
+                    editor.select_brush(-lines.signum() as i32)
+                },
+            Event::WindowEvent { event: WindowEvent::KeyboardInput { input: keyboard_input, .. }, .. }
                 => {
+                let pressed = keyboard_input.state == winit::event::ElementState::Pressed;
+                let tab = (Some(VirtualKeyCode::Tab) == keyboard_input.virtual_keycode) & pressed;
                 match &mut game_state {
-                    GameState::WorldLoaded(world) => {
-                        if (Some(VirtualKeyCode::J) == input.virtual_keycode) 
-                        & (input.state == winit::event::ElementState::Pressed)
-                        {
-                            match world.state {
-                                WorldState::Victory(_, time) if time > GAME_END_WAIT_TIME => {
-                                    if levels.next() {
-                                        *world = levels.load()
-                                    } else {
-                                        game_state = GameState::Victory
+                    GameState::Editor(editor) => {
+                        if tab {
+                            match level::load(&editor.level_string()) {
+                                Ok(world) => {
+                                    if let GameState::Editor(editor) = std::mem::replace(&mut game_state, GameState::Victory) {
+                                        game_state = GameState::PlayTest(world, editor)
                                     }
                                 },
-                                WorldState::Loss(_, time) if time > GAME_END_WAIT_TIME
-                                    => *world = levels.load(),
-                                _ => world.input(input)
+                                Err(err) => println!("Failed to load level - {}", err)
                             }
                         } else {
-                            world.input(input)
+                            editor.input(keyboard_input)
                         }
                     },
-                    GameState::ShowControls => {
-                        if input.virtual_keycode.is_some() & (input.state == winit::event::ElementState::Pressed) {
-                            game_state = GameState::WorldLoaded(levels.load())
+                    GameState::Controls { selected, rebinding, .. } if *rebinding & pressed => {
+                        if input.bind(ACTIONS[*selected], keyboard_input) {
+                            *rebinding = false
                         }
                     },
-                    GameState::Victory => ()
+                    GameState::PlayTest(..) if tab => {
+                        if let GameState::PlayTest(_, editor) = std::mem::replace(&mut game_state, GameState::Victory) {
+                            game_state = GameState::Editor(editor)
+                        }
+                    },
+                    _ => {
+                        for event in input.keyboard(keyboard_input) {
+                            handle_action(&mut game_state, &mut levels, &progress, &mut audio, control_flow, event)
+                        }
+                    }
                 }
             },
             Event::MainEventsCleared 
                 => {
+                    if let (Some(watcher), true) = (&mut level_watcher, last_level_watch.elapsed() >= TIME_BETWEEN_LEVEL_WATCHES) {
+                        last_level_watch = Time::now();
+                        for (index, level_string) in watcher.changed() {
+                            levels.set(index, level_string);
+                            if index == levels.index() {
+                                reload_level(&mut game_state, &levels, keep_position);
+                            }
+                        }
+                    }
                     if last_update.elapsed() >= TIME_BETWEEN_UPDATES {
                         last_update.add(TIME_BETWEEN_UPDATES);
-                        if let GameState::WorldLoaded(world) = &mut game_state {
-                            world.update();
+                        for event in input.poll_gamepads() {
+                            handle_action(&mut game_state, &mut levels, &progress, &mut audio, control_flow, event)
+                        }
+                        // Also outside of levels, so presses in menus don't carry over
+                        let control = input.control();
+                        match &mut game_state {
+                            GameState::WorldLoaded(world) if input.is_held(Action::Rewind) => world.step_back(),
+                            GameState::WorldLoaded(world) => {
+                                let was_running = matches!(world.state, WorldState::Running);
+                                world.update(control);
+                                let ended = !matches!(world.state, WorldState::Running);
+                                if was_running {
+                                    match world.state {
+                                        WorldState::Victory(..) => progress.complete(levels.current(), world.time),
+                                        WorldState::Loss(..) => progress.death(levels.current()),
+                                        WorldState::Running => ()
+                                    }
+                                    if ended {
+                                        progress.save();
+                                    }
+                                }
+                                if let (Some(path), true) = (&record_path, was_running & ended & world.replayable) {
+                                    let replay = Replay {
+                                        level_string: levels.current().to_string(),
+                                        inputs: world.inputs.clone()
+                                    };
+                                    if let Err(err) = std::fs::write(path, replay.encode()) {
+                                        println!("Failed to write replay file {}: {}", path, err);
+                                    }
+                                }
+                            },
+                            GameState::Replay(world, replay) => {
+                                world.update(replay.inputs.get(world.inputs.len()).copied().unwrap_or_default());
+                            },
+                            GameState::Editor(editor) => editor.update(),
+                            GameState::PlayTest(world, _) if input.is_held(Action::Rewind) => world.step_back(),
+                            GameState::PlayTest(world, _) => world.update(control),
+                            _ => ()
+                        }
+                        match &mut game_state {
+                            GameState::WorldLoaded(world) | GameState::Replay(world, _) | GameState::PlayTest(world, _)
+                            | GameState::Paused(world, _) => {
+                                for sound in world.sounds.drain(..) {
+                                    audio.play(sound);
+                                }
+                                audio.set_music(Some(world.meta.music.as_deref().unwrap_or(audio::DEFAULT_MUSIC)),
+                                    world.meta.music_volume.unwrap_or(1.0));
+                            },
+                            _ => audio.set_music(None, 1.0)
                         }
                     }
                     let since_last_frame = last_frame.elapsed();
                     if since_last_frame >= MIN_TIME_BETWEEN_FRAMES {
                         last_frame.add(MIN_TIME_BETWEEN_FRAMES);
                         match &game_state {
-                            GameState::WorldLoaded(world) 
+                            GameState::WorldLoaded(world) | GameState::Replay(world, _) | GameState::PlayTest(world, _)
                                 => world.render(&mut renderer, since_last_frame / TIME_BETWEEN_UPDATES),
-                            GameState::ShowControls => render_show_controls(&mut renderer),
+                            GameState::Paused(world, selected) => {
+                                world.draw(&mut renderer, 1.0);
+                                draw_pause_menu(&mut renderer, *selected, &audio);
+                                renderer.render();
+                            },
+                            GameState::ShowControls => render_controls(&mut renderer, &input, None),
+                            GameState::Controls { selected, rebinding, .. }
+                                => render_controls(&mut renderer, &input, Some((*selected, *rebinding))),
+                            GameState::LevelSelect => render_level_select(&mut renderer, &levels, &progress),
+                            GameState::LevelError(message) => render_level_error(&mut renderer, &input, message),
+                            GameState::Editor(editor) => editor.render(&mut renderer),
                             GameState::Victory => render_victory(&mut renderer)
                         }
                     }
@@ -155,6 +362,178 @@ pub fn main() {
     })
 }
 
+/// Reacts to player input everywhere except in the editor
+fn handle_action(game_state: &mut GameState, levels: &mut Levels, progress: &Progress, audio: &mut Audio,
+    control_flow: &mut ControlFlow, event: ActionEvent)
+{
+    if !event.pressed {
+        return
+    }
+    match &mut *game_state {
+        GameState::WorldLoaded(world) => {
+            match (event.action, world.state) {
+                (Action::Confirm, WorldState::Victory(_, time)) if time > GAME_END_WAIT_TIME => {
+                    if levels.next() {
+                        *game_state = load_level(levels)
+                    } else {
+                        *game_state = GameState::Victory
+                    }
+                },
+                (Action::Confirm, WorldState::Loss(_, time)) if time > GAME_END_WAIT_TIME => {
+                    let snapshot = world.snapshot.take();
+                    *game_state = load_level(levels);
+                    if let (GameState::WorldLoaded(world), Some(snapshot)) = (game_state, snapshot) {
+                        world.restore(&snapshot);
+                    }
+                },
+                (Action::Pause, WorldState::Running) => {
+                    if let GameState::WorldLoaded(world) = std::mem::replace(game_state, GameState::Victory) {
+                        *game_state = GameState::Paused(world, 0)
+                    }
+                },
+                _ => ()
+            }
+        },
+        GameState::Paused(_, selected) => {
+            let option = match event.action {
+                Action::Pause => Some(PauseOption::Resume),
+                Action::Flap => {
+                    *selected = selected.saturating_sub(1);
+                    None
+                },
+                Action::Dive => {
+                    *selected = (*selected + 1).min(PAUSE_OPTIONS.len() - 1);
+                    None
+                },
+                Action::MoveLeft | Action::MoveRight if matches!(PAUSE_OPTIONS[*selected], PauseOption::Volume) => {
+                    let step = if event.action == Action::MoveLeft { -0.1 } else { 0.1 };
+                    audio.set_volume(((audio.volume() + step) * 10.0).round() / 10.0);
+                    None
+                },
+                Action::Confirm => Some(PAUSE_OPTIONS[*selected]),
+                _ => None
+            };
+            match option {
+                Some(PauseOption::Resume) => {
+                    if let GameState::Paused(world, _) = std::mem::replace(game_state, GameState::Victory) {
+                        *game_state = GameState::WorldLoaded(world)
+                    }
+                },
+                Some(PauseOption::Restart) => *game_state = load_level(levels),
+                Some(PauseOption::Controls) => show_controls_menu(game_state),
+                Some(PauseOption::LevelSelect) => *game_state = GameState::LevelSelect,
+                Some(PauseOption::Quit) => *control_flow = ControlFlow::Exit,
+                Some(PauseOption::Volume) | None => ()
+            }
+        },
+        GameState::Replay(world, _) => {
+            // Restart the replay once it's over
+            let ended = match world.state {
+                WorldState::Running => false,
+                WorldState::Loss(_, time) | WorldState::Victory(_, time) => time > GAME_END_WAIT_TIME
+            };
+            if ended & (event.action == Action::Confirm) {
+                *world = levels.load().expect("Replay level loaded before")
+            }
+        },
+        GameState::ShowControls => {
+            if event.action == Action::Pause {
+                show_controls_menu(game_state)
+            } else {
+                // Continue where the player left off
+                while progress.get(levels.current()).completed && levels.next() {}
+                *game_state = GameState::LevelSelect
+            }
+        },
+        GameState::Controls { selected, rebinding, .. } => {
+            match event.action {
+                Action::Flap => *selected = selected.saturating_sub(1),
+                Action::Dive => *selected = (*selected + 1).min(ACTIONS.len() - 1),
+                Action::Confirm => *rebinding = true,
+                Action::Pause => {
+                    if let GameState::Controls { previous, .. } = std::mem::replace(game_state, GameState::Victory) {
+                        *game_state = *previous
+                    }
+                },
+                _ => ()
+            }
+        },
+        GameState::LevelSelect => {
+            match event.action {
+                Action::MoveLeft => levels.select(levels.index().saturating_sub(1)),
+                Action::MoveRight => levels.select(levels.index() + 1),
+                Action::Flap => levels.select(levels.index().saturating_sub(LEVEL_SELECT_COLUMNS)),
+                Action::Dive => levels.select(levels.index() + LEVEL_SELECT_COLUMNS),
+                Action::Confirm => *game_state = load_level(levels),
+                _ => ()
+            }
+        },
+        GameState::LevelError(_) => {
+            // Skip the broken level
+            if (event.action == Action::Confirm) & levels.next() {
+                *game_state = load_level(levels)
+            }
+        },
+        GameState::PlayTest(world, editor) => {
+            let ended = match world.state {
+                WorldState::Running => false,
+                WorldState::Loss(_, time) | WorldState::Victory(_, time) => time > GAME_END_WAIT_TIME
+            };
+            if ended & (event.action == Action::Confirm) {
+                let snapshot = match world.state {
+                    WorldState::Loss(..) => world.snapshot.take(),
+                    _ => None
+                };
+                *world = level::load(&editor.level_string()).expect("Level loaded before");
+                if let Some(snapshot) = snapshot {
+                    world.restore(&snapshot);
+                }
+            }
+        },
+        GameState::Editor(_) => (),
+        GameState::Victory => *game_state = GameState::LevelSelect
+    }
+}
+
+fn show_controls_menu(game_state: &mut GameState) {
+    let previous = std::mem::replace(game_state, GameState::Victory);
+    *game_state = GameState::Controls {
+        previous: Box::new(previous),
+        selected: 0,
+        rebinding: false
+    }
+}
+
+fn load_level(levels: &Levels) -> GameState {
+    match levels.load() {
+        Ok(world) => GameState::WorldLoaded(world),
+        Err(err) => GameState::LevelError(err.to_string())
+    }
+}
+
+/// Replaces the world after its level string changed, also behind the pause and controls menus
+fn reload_level(game_state: &mut GameState, levels: &Levels, keep_position: bool) {
+    match game_state {
+        GameState::WorldLoaded(old_world) | GameState::Paused(old_world, _) => {
+            match levels.load() {
+                Ok(mut world) => {
+                    if keep_position & matches!(old_world.state, WorldState::Running) {
+                        let old_pos = old_world.entities.get::<Pos>(old_world.player).unwrap().curr;
+                        *world.entities.get_mut::<Pos>(world.player).unwrap() = old_pos.into();
+                        world.camera.pos = old_world.camera.pos;
+                        world.camera.size = old_world.camera.size;
+                    }
+                    *old_world = world
+                },
+                Err(err) => *game_state = GameState::LevelError(err.to_string())
+            }
+        },
+        GameState::Controls { previous, .. } => reload_level(previous, levels, keep_position),
+        GameState::LevelError(_) => *game_state = load_level(levels),
+        _ => ()
+    }
+}
+
 fn get_icon() -> winit::window::Icon {
     use image::GenericImageView;
     let image = image::load_from_memory(include_bytes!("../icon.png")).unwrap();
@@ -208,58 +587,164 @@ pub struct Camera {
 
 pub struct World {
     state: WorldState,
-    pressed_keys: HashMap<VirtualKeyCode, bool>,
     entities: hecs::World,
     player: Entity,
-    cary: Entity,
     camera: Camera,
     /// In-game time in seconds
-    time: f32
+    time: f32,
+    /// Player input of each update so far, for recording replays
+    inputs: Vec<Controllable>,
+    /// Taken when a walker passed the last checkpoint
+    snapshot: Option<Snapshot>,
+    /// Attempts started from a checkpoint or rewound can't be replayed from the level's start
+    replayable: bool,
+    rewind: Rewind,
+    particles: Particles,
+    /// Played and cleared by the main loop
+    sounds: Vec<Sound>,
+    meta: LevelMeta,
+    colliders: Grid,
+    hazzards: Grid,
+    /// By detect bounds
+    carryables: Grid,
+    /// Reused by update_grids() instead of allocating each update, empty in between
+    moving: Vec<Entity>,
+    /// Reused by update_physics() like moving
+    physics_order: Vec<(f32, Entity)>,
+    /// Reused by update_paths() like moving
+    moved_on_paths: Vec<(Entity, Vec2, Vec2)>
 }
 
 impl World {
     fn new() -> Self {
         let mut entities = hecs::World::new();
         let player = entities.spawn(make_player(Vec2(0.0, 0.0)));
-        let cary = entities.spawn(make_cary(Vec2(0.0, 0.0)));
 
         World {
             state: WorldState::Running,
-            pressed_keys: HashMap::new(),
             entities,
             player,
-            cary,
             camera: Camera { pos: Vec2::zero(), size: 7.0 },
-            time: 0.0
+            time: 0.0,
+            inputs: Vec::new(),
+            snapshot: None,
+            replayable: true,
+            rewind: Rewind::default(),
+            particles: Particles::default(),
+            sounds: Vec::new(),
+            meta: LevelMeta::default(),
+            colliders: Grid::default(),
+            hazzards: Grid::default(),
+            carryables: Grid::default(),
+            moving: Vec::new(),
+            physics_order: Vec::new(),
+            moved_on_paths: Vec::new()
+        }
+    }
+
+    /// Adds the entity to the grids it belongs in, or moves it there
+    fn index(&mut self, entity: Entity) {
+        let pos = match self.entities.get::<Pos>(entity) {
+            Ok(pos) => pos.curr,
+            Err(_) => return
+        };
+        if let Ok(collider) = self.entities.get::<Collider>(entity) {
+            self.colliders.insert(entity, collider.bounds + pos);
+        }
+        if let Ok(hazzard) = self.entities.get::<Hazzard>(entity) {
+            self.hazzards.insert(entity, hazzard.bounds + pos);
+        }
+        if let Ok(carryable) = self.entities.get::<Carryable>(entity) {
+            self.carryables.insert(entity, carryable.detect_bounds + pos);
         }
     }
 
+    fn index_all(&mut self) {
+        let entities: Vec<_> = self.entities.iter().map(|(entity, _)|entity).collect();
+        for entity in entities {
+            self.index(entity);
+        }
+    }
+
+    fn despawn(&mut self, entity: Entity) {
+        // Keep ChildOf and Children in sync
+        if let Ok(child_of) = self.entities.get::<ChildOf>(entity) {
+            let parent = child_of.parent;
+            drop(child_of);
+            if let Ok(mut children) = self.entities.get_mut::<Children>(parent) {
+                children.0.retain(|child| *child != entity);
+            }
+            if let Ok(mut player) = self.entities.get_mut::<Player>(parent) {
+                if player.carrying == Some(entity) {
+                    player.carrying = None;
+                }
+            }
+        }
+        self.unindex(entity);
+        let _ = self.entities.despawn(entity);
+    }
+
+    fn unindex(&mut self, entity: Entity) {
+        self.colliders.remove(entity);
+        self.hazzards.remove(entity);
+        self.carryables.remove(entity);
+    }
+
+    /// Static entities are indexed once, this updates those that can move
+    fn update_grids(&mut self) {
+        let mut moving = std::mem::take(&mut self.moving);
+        moving.extend(self.entities.iter()
+            .filter(|(_, entity)|entity.get::<Physics>().is_some() | entity.get::<ChildOf>().is_some())
+            .map(|(entity, _)|entity));
+        for &entity in &moving {
+            self.index(entity);
+        }
+        moving.clear();
+        self.moving = moving;
+    }
+
     pub fn query<Q: hecs::Query>(&self) -> hecs::QueryBorrow<'_, Q> {
         self.entities.query::<Q>()
     }
 
-    fn input(&mut self, input: winit::event::KeyboardInput) {
-        if let Some(keycode) = input.virtual_keycode {
-            if input.state == winit::event::ElementState::Pressed {
-                self.pressed_keys.insert(keycode, true);
-            } else {
-                self.pressed_keys.remove(&keycode);
-            }
+    fn update(&mut self, control: Controllable) {
+        if let WorldState::Running = self.state {
+            *self.entities.get_mut::<Controllable>(self.player).unwrap() = control;
+        }
+        self.update_simulation();
+    }
+
+    /// Goes back one update, as far as the rewind buffer reaches
+    fn step_back(&mut self) {
+        if let WorldState::Victory(..) = self.state {
+            return
+        }
+        let mut rewind = std::mem::take(&mut self.rewind);
+        if rewind.step_back(self) {
+            self.replayable = false;
         }
+        self.rewind = rewind;
     }
 
-    fn update(&mut self) {
+    fn update_simulation(&mut self) {
         match self.state {
             WorldState::Running => {
+                let mut rewind = std::mem::take(&mut self.rewind);
+                rewind.record(self);
+                self.rewind = rewind;
                 self.update_position_interpol();
                 self.time += TIME_BETWEEN_UPDATES;
-                self.update_player_input();
+                self.inputs.push(*self.entities.get::<Controllable>(self.player).unwrap());
                 self.update_player();
-                self.update_cary();
+                self.update_walkers();
+                self.update_paths();
                 self.update_physics();
+                self.update_signals();
+                self.update_invulnerability();
                 self.update_hazzards();
                 self.update_shooters();
                 self.update_remove_on_impact();
+                self.update_checkpoints();
                 self.update_exits();
                 self.update_animations();
                 self.update_camera();
@@ -271,31 +756,7 @@ impl World {
                 *time += TIME_BETWEEN_UPDATES;
             }
         }
-    }
-
-    fn update_player_input(&mut self) {
-        // TODO: figure out scancodes & use them instead
-        use VirtualKeyCode::*;
-        let mut control = self.entities.get_mut::<Controllable>(self.player).unwrap();
-        control.vertical = match (self.pressed_keys.get(&W), self.pressed_keys.get(&S)) {
-            (Some(true), Some(true)) => Vertical::None,
-            (Some(false), Some(false)) => Vertical::None,
-            (Some(_), _) => Vertical::Up,
-            (_, Some(_)) => Vertical::Down,
-            (None, None) => Vertical::None
-        };
-        control.horizontal = match (self.pressed_keys.get(&A), self.pressed_keys.get(&D)) {
-            (Some(true), Some(true)) => Horizontal::None,
-            (Some(false), Some(false)) => Horizontal::None,
-            (Some(_), _) => Horizontal::Left,
-            (_, Some(_)) => Horizontal::Right,
-            (None, None) => Horizontal::None
-        };
-        control.pick_up = *self.pressed_keys.get(&J).unwrap_or(&false);
-
-        for value in self.pressed_keys.values_mut() {
-            *value = false;
-        }
+        self.particles.update();
     }
 
     fn update_position_interpol(&mut self) {
@@ -325,6 +786,9 @@ impl World {
                 physics.vel.1 = (physics.vel.1 + flap_acc).min(max_speed_upwards);
                 sprite.tex = textures::PLAYER_FLY;
                 sprite.timer = 0.0;
+                let pos = self.entities.get::<Pos>(self.player).unwrap().curr;
+                self.particles.emit(&particles::DUST, pos + Vec2(0.0, -0.3), Vec2(0.0, -1.0));
+                self.sounds.push(Sound::Flap);
             },
             Vertical::None if sprite.finished() => {
                 sprite.tex = if physics.vel.1 < -0.3*dive_strenght {textures::PLAYER_DIVE} else {textures::PLAYER_IDLE};
@@ -364,6 +828,7 @@ impl World {
                 self.entities.get_mut::<Pos>(self.player).unwrap().curr.1 += 0.07; // Ensure we can't drop into a collider we were just carrying
                 self.entities.get_mut::<Carryable>(carried).unwrap().carried = false;
                 self.entities.remove_one::<ChildOf>(carried).unwrap();
+                self.sounds.push(Sound::Drop);
             } else if let Some(to_be_carried) = self.find_pickupable() {
                 player.carrying = Some(to_be_carried);
                 children.0.push(to_be_carried);
@@ -380,174 +845,746 @@ impl World {
                         collision: child_bounds
                     }
                 ).unwrap();
+                self.sounds.push(Sound::PickUp);
             }
         }
     }
 
     fn find_pickupable(&self) -> Option<Entity> {
-        let player_pos = self.entities.get::<Pos>(self.player).unwrap();
-        for (entity, (pos, carryable)) in self.query::<(&Pos, &Carryable)>().iter() {
-            if (carryable.detect_bounds + pos.curr).contains(player_pos.curr) {
-                return Some(entity)
+        let player_pos = self.entities.get::<Pos>(self.player).unwrap().curr;
+        for entity in self.carryables.query(Bounds { min: player_pos, max: player_pos }) {
+            let mut query = self.entities.query_one::<(&Pos, &Carryable)>(entity).unwrap();
+            if let Some((pos, carryable)) = query.get() {
+                if (carryable.detect_bounds + pos.curr).contains(player_pos) {
+                    return Some(entity)
+                }
             }
         }
         None
     }
 
-    fn update_cary(&mut self) {
-        if !self.entities.get::<Carryable>(self.cary).unwrap().carried {
-            let mut cary = self.entities.get_mut::<Cary>(self.cary).unwrap();
-            let pos = self.entities.get_mut::<Pos>(self.cary).unwrap();
-            let mut physics = self.entities.get_mut::<Physics>(self.cary).unwrap();
+    /// Cary and the other NPCs to escort, which haven't reached an exit yet
+    fn walkers(&self) -> Vec<Entity> {
+        self.entities.query::<&Cary>().iter().map(|(entity, _)|entity).collect()
+    }
+
+    fn update_walkers(&mut self) {
+        for walker in self.walkers() {
+            self.update_walker(walker);
+        }
+    }
+
+    fn update_walker(&mut self, walker: Entity) {
+        if !self.entities.get::<Carryable>(walker).unwrap().carried {
+            let walking = self.entities.get::<Walking>(walker).unwrap();
+            let mut cary = self.entities.get_mut::<Cary>(walker).unwrap();
+            let pos = self.entities.get_mut::<Pos>(walker).unwrap();
+            let mut physics = self.entities.get_mut::<Physics>(walker).unwrap();
+
+            let direction = if cary.walk_right { 1.0 } else { -1.0 };
+            let grounded = physics.collided.1 == Vertical::Down;
+            let bounds = physics.bounds + pos.curr;
 
-            let walk_speed = 1.8;
-            let jump_speed = 6.7;
-            let jump_speed_low = 5.0;
+            let stopped = walking.stop_at_hazzards & !self.is_safe(&(bounds + Vec2(direction * 0.3, 0.0)));
             // Allow movement during junp
-            if (physics.collided.1 == Vertical::Down) | (physics.vel.1 > 0.0) {
-                physics.vel.0 = if cary.walk_right { walk_speed} else { -walk_speed };
+            if grounded | (physics.vel.1 > 0.0) {
+                physics.vel.0 = if stopped { 0.0 } else { direction * walking.speed };
             }
-            if (physics.collided.1 == Vertical::Down) & 
+
+            // Look for ground in a thin column just ahead
+            let mut turn = match walking.max_drop {
+                Some(max_drop) if grounded & !stopped => {
+                    let front = if cary.walk_right { bounds.max.0 } else { bounds.min.0 };
+                    self.is_free(&Bounds {
+                        min: Vec2(front.min(front + direction * 0.1), bounds.min.1 - max_drop as f32 - 0.5),
+                        max: Vec2(front.max(front + direction * 0.1), bounds.min.1 - 0.05)
+                    })
+                },
+                _ => false
+            };
+            if grounded & 
                 (((physics.collided.0 == Horizontal::Left) & !cary.walk_right) 
                 |((physics.collided.0 == Horizontal::Right) & cary.walk_right))
             {
                 // Decide whether to jump or to turn around
-                let check_offset_x = if cary.walk_right {0.2} else {-0.2};
-                if self.is_free(&(physics.bounds + pos.curr + Vec2(check_offset_x, 0.1))) {
-                    physics.vel.1 = 1.5;
-                } else if self.is_free(&(physics.bounds + pos.curr + Vec2(check_offset_x, 1.1))) {
-                    physics.vel.1 = jump_speed_low;
-                } else if self.is_free(&(physics.bounds + pos.curr + Vec2(check_offset_x, 2.1))) {
-                    physics.vel.1 = jump_speed;
-                } else {
-                    let mut sprite = self.entities.get_mut::<Sprite>(self.cary).unwrap();
-                    sprite.mirror = cary.walk_right;
-                    cary.walk_right ^= true;
+                let check_offset = Vec2(direction * 0.2, 0.0);
+                let height = (0..=walking.jump_height)
+                    .find(|height|self.is_free(&(bounds + check_offset + Vec2(0.0, *height as f32 + 0.1))));
+                match height {
+                    Some(0) => physics.vel.1 = 1.5,
+                    Some(1) => {
+                        physics.vel.1 = walking.jump_speed_low;
+                        self.sounds.push(Sound::Jump);
+                    },
+                    Some(_) => {
+                        physics.vel.1 = walking.jump_speed;
+                        self.sounds.push(Sound::Jump);
+                    },
+                    None => turn = true
                 }
             }
+            if turn {
+                let mut sprite = self.entities.get_mut::<Sprite>(walker).unwrap();
+                sprite.mirror = cary.walk_right;
+                cary.walk_right ^= true;
+                self.sounds.push(Sound::Turn);
+            }
         }
     }
 
     fn is_free(&self, bounds: &Bounds) -> bool {
-        for (_, (collision_pos, collider)) in self.query::<(&Pos, &Collider)>().iter() {
-            if bounds.overlapps(collider.bounds + collision_pos.curr) {
-                return false
+        self.colliders.query(*bounds).into_iter().all(|entity| {
+            let mut query = self.entities.query_one::<(&Pos, &Collider)>(entity).unwrap();
+            match query.get() {
+                Some((pos, collider)) => !bounds.overlapps(collider.bounds + pos.curr),
+                None => true
             }
-        }
-        true
+        })
+    }
+
+    /// Whether no hazzard overlaps the bounds
+    fn is_safe(&self, bounds: &Bounds) -> bool {
+        self.hazzards.query(*bounds).into_iter().all(|entity| {
+            let mut query = self.entities.query_one::<(&Pos, &Hazzard)>(entity).unwrap();
+            match query.get() {
+                Some((pos, hazzard)) => !bounds.overlapps(hazzard.bounds + pos.curr),
+                None => true
+            }
+        })
     }
 
     fn update_physics(&mut self) {
         const GRAVITY: f32 = 10.0;
         const TERMINAL_VELOCITY: f32 = 12.0;
         const GROUND_FRICTION: f32 = 4.5;
-        for (entity, physics) in self.query::<&mut Physics>().iter() {
+        const DUSTY_LANDING_SPEED: f32 = 3.0;
+        let mut landings = Vec::new();
+        // Bottom up, so that stacked bodies collide with where the ones below them have already moved
+        let mut order = std::mem::take(&mut self.physics_order);
+        order.extend(self.query::<(&Pos, &Physics)>().iter()
+            .map(|(entity, (pos, physics))|((physics.bounds + pos.curr).min.1, entity)));
+        order.sort_by(|a, b|a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal).then(a.1.cmp(&b.1)));
+
+        for &(_, entity) in &order {
+            let mut physics_query = self.entities.query_one::<&mut Physics>(entity).unwrap();
+            let physics = physics_query.get().unwrap();
             if physics.gravity & (physics.vel.1 > -TERMINAL_VELOCITY) {
                 physics.vel.1 -= GRAVITY * TIME_BETWEEN_UPDATES;
             }
 
-            let mut movement = physics.vel * TIME_BETWEEN_UPDATES;
             physics.collided = (Horizontal::None, Vertical::None);
-            let bounds = physics.bounds + self.entities.get::<Pos>(entity).unwrap().curr;
+            physics.contact_normal = None;
+            let pos = self.entities.get::<Pos>(entity).unwrap().curr;
             let children = self.entities.get::<Children>(entity).ok();
             let entities = &self.entities;
-            for (collision_entity, (collision_pos, collider)) in self.query::<(&Pos, &Collider)>().iter() {
-                let collision = collider.bounds + collision_pos.curr;
-                for bounds in Some(bounds).into_iter().chain(children.iter().flat_map(
-                    |children| children.0.iter().filter_map(
-                        |child| Some(entities.get::<ChildOf>(*child).ok()?.collision
-                                            + entities.get::<Pos>(*child).ok()?.curr)
-                    ))) 
-                {
-                    if self.entities.get::<Carryable>(collision_entity).map_or(true, |carryable|!carryable.carried) {
-                        if !bounds.overlapps(collision) & (bounds + movement).overlapps(collision) {
-                            if (bounds + Vec2(movement.0, 0.0)).overlapps(collision) {
-                                physics.collided.0 = if movement.0 > 0.0 { Horizontal::Right } else { Horizontal::Left };
-                                physics.vel.0 = 0.0;
-                                movement.0 = 0.0;
-                            }
-                            if (bounds + Vec2(0.0, movement.1)).overlapps(collision) {
-                                physics.collided.1 = if movement.1 > 0.0 { Vertical::Up } else { Vertical::Down };
-                                physics.vel.1 = 0.0;
-                                physics.vel.0 *= 1.0 - GROUND_FRICTION * TIME_BETWEEN_UPDATES;
-                                movement.1 = 0.0;
-                            }
-                            /* // somehow prevents walking in -x direction?!
-                            if (movement.0 != 0.0) & (movement.1 != 0.0) {
-                                // Corner hit head on
-                                movement = Vec2::zero()
+            // The body and what it carries
+            let body: Vec<_> = Some(physics.bounds + pos).into_iter().chain(children.iter().flat_map(
+                |children| children.0.iter().filter_map(
+                    |child| Some(entities.get::<ChildOf>(*child).ok()?.collision
+                                        + entities.get::<Pos>(*child).ok()?.curr)
+                ))).collect();
+            let can_push = entities.get::<Pusher>(entity).is_ok();
+            // Carried blocks can't collide with anything, but they still shield from bullets
+            let is_bullet = entities.get::<RemoveOnImpact>(entity).is_ok();
+
+            let mut movement = Vec2::zero();
+            let mut remaining = physics.vel * TIME_BETWEEN_UPDATES;
+            // Everything that can be reached during this update. Sliding never moves further than remaining.
+            let mut collisions = Vec::new();
+            for bounds in &body {
+                let reach = Bounds {
+                    min: bounds.min + Vec2(remaining.0.min(0.0), remaining.1.min(0.0)) - Vec2(CONTACT_SKIN, CONTACT_SKIN),
+                    max: bounds.max + Vec2(remaining.0.max(0.0), remaining.1.max(0.0)) + Vec2(CONTACT_SKIN, CONTACT_SKIN)
+                };
+                for collision_entity in self.colliders.query(reach) {
+                    if (collision_entity == entity) 
+                        | (!is_bullet & matches!(entities.get::<Carryable>(collision_entity), Ok(carryable) if carryable.carried))
+                    {
+                        continue
+                    }
+                    let mut query = entities.query_one::<(&Pos, &Collider)>(collision_entity).unwrap();
+                    if let Some((collision_pos, collider)) = query.get() {
+                        collisions.push((collision_entity, collider.bounds + collision_pos.curr));
+                    }
+                }
+                // Pushable blocks can rest on those who push them
+                if entities.get::<Pushable>(entity).is_ok() {
+                    for (pusher_entity, (pusher_pos, pusher)) in entities.query::<(&Pos, &Pusher)>().iter() {
+                        let carried = matches!(entities.get::<Carryable>(pusher_entity), Ok(carryable) if carryable.carried);
+                        if (pusher_entity != entity) & !carried & reach.overlapps(pusher.bounds + pusher_pos.curr) {
+                            collisions.push((pusher_entity, pusher.bounds + pusher_pos.curr));
+                        }
+                    }
+                }
+            }
+            // Slide along what was hit. There can be one hit per axis, a third only lands on a corner.
+            for _ in 0..3 {
+                let mut first_hit: Option<(Hit, usize)> = None;
+                for (index, &(_, collision)) in collisions.iter().enumerate() {
+                    for bounds in &body {
+                        if let Some(hit) = (*bounds + movement).sweep(remaining, collision) {
+                            // On ties, the first collider found wins
+                            let earlier = match first_hit {
+                                Some((first_hit, _)) => hit.time < first_hit.time,
+                                None => true
+                            };
+                            if earlier {
+                                first_hit = Some((hit, index));
                             }
-                            */
                         }
                     }
                 }
+                let (hit, index) = match first_hit {
+                    Some(hit) => hit,
+                    None => {
+                        movement += remaining;
+                        break
+                    }
+                };
+                movement += remaining * hit.time + hit.normal * CONTACT_SKIN;
+                remaining *= 1.0 - hit.time;
+                if hit.normal.0 != 0.0 {
+                    let (hit_entity, _) = collisions[index];
+                    let pushed = if can_push & self.entities.get::<Pushable>(hit_entity).is_ok() {
+                        self.push(hit_entity, Vec2(remaining.0, 0.0), entity)
+                    } else {
+                        Vec2::zero()
+                    };
+                    if pushed.0 != 0.0 {
+                        if let Ok(collider) = self.entities.get::<Collider>(hit_entity) {
+                            let pushed_pos = self.entities.get::<Pos>(hit_entity).unwrap().curr;
+                            self.colliders.insert(hit_entity, collider.bounds + pushed_pos);
+                        }
+                        collisions[index].1 += pushed;
+                        movement += pushed;
+                    }
+                    // Only stop when the block is stuck as well
+                    if (remaining.0 - pushed.0).abs() > CONTACT_SKIN {
+                        physics.collided.0 = if hit.normal.0 < 0.0 { Horizontal::Right } else { Horizontal::Left };
+                        physics.vel.0 = 0.0;
+                    }
+                    remaining.0 = 0.0;
+                } else {
+                    if (hit.normal.1 > 0.0) & (physics.vel.1 < -DUSTY_LANDING_SPEED) & entities.get::<Pushable>(entity).is_ok() {
+                        landings.push(entity);
+                    }
+                    physics.collided.1 = if hit.normal.1 < 0.0 { Vertical::Up } else { Vertical::Down };
+                    physics.vel.1 = 0.0;
+                    physics.vel.0 *= 1.0 - GROUND_FRICTION * TIME_BETWEEN_UPDATES;
+                    remaining.1 = 0.0;
+                }
+                physics.contact_normal = Some(hit.normal);
             }
             self.entities.get_mut::<Pos>(entity).unwrap().curr += movement;
+            // Later bodies in this update need to collide with the new position
+            if let Ok(collider) = self.entities.get::<Collider>(entity) {
+                self.colliders.insert(entity, collider.bounds + pos + movement);
+            }
         }
+        order.clear();
+        self.physics_order = order;
 
         // Children
         for (_, (pos, child_of)) in self.query::<(&mut Pos, &ChildOf)>().iter() {
             let parent_pos = self.entities.get::<Pos>(child_of.parent).unwrap();
             pos.curr = parent_pos.curr + child_of.offset;
         }
+        self.update_grids();
+
+        for entity in landings {
+            let bounds = self.entities.get::<Physics>(entity).unwrap().bounds + self.entities.get::<Pos>(entity).unwrap().curr;
+            self.particles.emit(&particles::DUST, Vec2(bounds.center().0, bounds.min.1), Vec2(0.0, 1.0));
+        }
+    }
+
+    /// Moves path followers, and the bodies riding on or pushed by those that are colliders
+    fn update_paths(&mut self) {
+        let mut moved = std::mem::take(&mut self.moved_on_paths);
+        for (entity, (pos, path)) in self.query::<(&mut Pos, &mut PathFollower)>().iter() {
+            let old_pos = pos.curr;
+            pos.curr = path.step(pos.curr, TIME_BETWEEN_UPDATES);
+            moved.push((entity, old_pos, pos.curr - old_pos));
+        }
+        for &(entity, old_pos, movement) in &moved {
+            self.index(entity);
+            let bounds = match self.entities.get::<Collider>(entity) {
+                Ok(collider) => collider.bounds,
+                Err(_) => continue
+            };
+            let old_bounds = bounds + old_pos;
+            let new_bounds = bounds + old_pos + movement;
+            let mut riders = Vec::new();
+            for (body, (body_pos, physics)) in self.query::<(&Pos, &Physics)>().iter() {
+                if self.entities.get::<ChildOf>(body).is_ok() {
+                    continue
+                }
+                let parts = self.body_parts(body, physics.bounds + body_pos.curr);
+                let resting = (physics.collided.1 == Vertical::Down) & parts.iter().any(|(_, part)|
+                    ((part.min.1 - old_bounds.max.1).abs() < 0.05)
+                    & (part.max.0 > old_bounds.min.0) & (part.min.0 < old_bounds.max.0));
+                let pushed = parts.iter().any(|(_, part)|part.overlapps(new_bounds));
+                if resting | pushed {
+                    riders.push((body, parts));
+                }
+            }
+            for (body, parts) in riders {
+                self.ride(body, &parts, movement, entity);
+            }
+        }
+        moved.clear();
+        self.moved_on_paths = moved;
+    }
+
+    /// Pressure plates send their signal while a body rests on them, which opens doors and retracts spikes
+    fn update_signals(&mut self) {
+        let mut signals = Vec::new();
+        for (_, (pos, plate, sprite)) in self.query::<(&Pos, &mut PressurePlate, &mut Sprite)>().iter() {
+            let bounds = plate.bounds + pos.curr;
+            // Carried bodies don't rest on anything, bullets fly over
+            plate.pressed = self.query::<(&Pos, &Physics)>().iter()
+                .filter(|(body, (_, physics))|self.entities.get::<ChildOf>(*body).is_err() & physics.gravity)
+                .any(|(_, (body_pos, physics))|(physics.bounds + body_pos.curr).overlapps(bounds));
+            sprite.tex = if plate.pressed { &textures::PLATE[1..] } else { &textures::PLATE[..1] };
+            if plate.pressed {
+                signals.push(plate.signal);
+            }
+        }
+        let mut switched = Vec::new();
+        for (entity, receiver) in self.query::<&Receiver>().iter() {
+            let active = signals.contains(&receiver.signal) != receiver.inverted;
+            if active != receiver.active {
+                switched.push((entity, active));
+            }
+        }
+        for (entity, active) in switched {
+            self.switch(entity, active);
+        }
+    }
+
+    /// Opens or closes a door, retracts or extends spikes
+    fn switch(&mut self, entity: Entity, active: bool) {
+        if let Ok(door) = self.entities.get::<Door>(entity).map(|door|door.0) {
+            let pos = self.entities.get::<Pos>(entity).unwrap().curr;
+            if active {
+                let _ = self.entities.remove_one::<Collider>(entity);
+                self.colliders.remove(entity);
+            } else {
+                // Stay open until the doorway is clear
+                let blocked = self.query::<(&Pos, &Physics)>().iter()
+                    .any(|(_, (body_pos, physics))|(physics.bounds + body_pos.curr).overlapps(door + pos));
+                if blocked {
+                    return
+                }
+                self.entities.insert_one(entity, Collider { bounds: door }).unwrap();
+                self.index(entity);
+            }
+            self.entities.get_mut::<Sprite>(entity).unwrap().tex = if active { &textures::DOOR[1..] } else { &textures::DOOR[..1] };
+        }
+        if let Ok(spikes) = self.entities.get::<Retractable>(entity).map(|spikes|spikes.0) {
+            let mut sprite = self.entities.get_mut::<Sprite>(entity).unwrap();
+            sprite.tex = if active { textures::SPIKES_RETRACTED } else { textures::SPIKES };
+            sprite.running = !active;
+            drop(sprite);
+            if active {
+                let _ = self.entities.remove_one::<Hazzard>(entity);
+                self.hazzards.remove(entity);
+            } else {
+                self.entities.insert_one(entity, Hazzard { bounds: spikes }).unwrap();
+                self.index(entity);
+            }
+        }
+        self.entities.get_mut::<Receiver>(entity).unwrap().active = active;
+    }
+
+    /// Bounds of a body and of what it carries
+    fn body_parts(&self, body: Entity, bounds: Bounds) -> Vec<(Entity, Bounds)> {
+        let mut parts = vec![(body, bounds)];
+        if let Ok(children) = self.entities.get::<Children>(body) {
+            for &child in &children.0 {
+                if let (Ok(child_of), Ok(pos)) = (self.entities.get::<ChildOf>(child), self.entities.get::<Pos>(child)) {
+                    parts.push((child, child_of.collision + pos.curr));
+                }
+            }
+        }
+        parts
+    }
+
+    /// Moves a body along with a platform, unless that would push it into something else
+    fn ride(&mut self, body: Entity, parts: &[(Entity, Bounds)], movement: Vec2, platform: Entity) {
+        for (_, part) in parts {
+            let moved = *part + movement;
+            for other in self.colliders.query(moved) {
+                if (other == platform) | parts.iter().any(|(part_entity, _)|*part_entity == other) {
+                    continue
+                }
+                let mut query = self.entities.query_one::<(&Pos, &Collider)>(other).unwrap();
+                if let Some((pos, collider)) = query.get() {
+                    if moved.overlapps(collider.bounds + pos.curr) {
+                        return
+                    }
+                }
+            }
+        }
+        self.entities.get_mut::<Pos>(body).unwrap().curr += movement;
+        self.index(body);
+    }
+
+    /// Moves a pushable block as far as it can go, returns how far that is
+    fn push(&self, entity: Entity, movement: Vec2, pusher: Entity) -> Vec2 {
+        let bounds = {
+            let mut query = self.entities.query_one::<(&Pos, &Collider)>(entity).unwrap();
+            match query.get() {
+                Some((pos, collider)) => collider.bounds + pos.curr,
+                None => return Vec2::zero()
+            }
+        };
+        let reach = Bounds {
+            min: bounds.min + Vec2(movement.0.min(0.0), movement.1.min(0.0)),
+            max: bounds.max + Vec2(movement.0.max(0.0), movement.1.max(0.0))
+        };
+        let mut first_hit: Option<Hit> = None;
+        for other in self.colliders.query(reach) {
+            if (other == entity) | (other == pusher) 
+                | matches!(self.entities.get::<Carryable>(other), Ok(carryable) if carryable.carried)
+            {
+                continue
+            }
+            let mut query = self.entities.query_one::<(&Pos, &Collider)>(other).unwrap();
+            if let Some((pos, collider)) = query.get() {
+                if let Some(hit) = bounds.sweep(movement, collider.bounds + pos.curr) {
+                    let earlier = match first_hit {
+                        Some(first_hit) => hit.time < first_hit.time,
+                        None => true
+                    };
+                    if earlier {
+                        first_hit = Some(hit);
+                    }
+                }
+            }
+        }
+        let moved = match first_hit {
+            Some(hit) => movement * hit.time + hit.normal * CONTACT_SKIN,
+            None => movement
+        };
+        self.entities.get_mut::<Pos>(entity).unwrap().curr += moved;
+        moved
     }
 
     fn update_hazzards(&mut self) {
+        let invulnerability_time = 1.5;
+        let knockback_speed = 6.0;
         let mut loss = None;
-        for (_, (pos, killable)) in self.query::<(&Pos, &Killable)>().iter() {
-            for (_, (hazzard_pos, hazzard)) in self.query::<(&Pos, &Hazzard)>().iter() {
-                if (killable.bounds + pos.curr).overlapps(hazzard.bounds + hazzard_pos.curr) {
+        let mut to_despawn = Vec::new();
+        let mut deaths = Vec::new();
+        let mut walker_died = None;
+        for (entity, (pos, killable, health, physics)) in 
+            self.query::<(&Pos, &Killable, Option<&mut Health>, Option<&mut Physics>)>().iter() 
+        {
+            if let Some(health) = &health {
+                if health.invulnerable > 0.0 {
+                    continue
+                }
+            }
+            let bounds = killable.bounds + pos.curr;
+            let mut hit = None;
+            for hazzard_entity in self.hazzards.query(bounds) {
+                let mut query = self.entities.query_one::<(&Pos, &Hazzard)>(hazzard_entity).unwrap();
+                let (hazzard_pos, hazzard) = match query.get() {
+                    Some(hazzard) => hazzard,
+                    None => continue
+                };
+                if bounds.overlapps(hazzard.bounds + hazzard_pos.curr) {
+                    hit = Some((hazzard_entity, hazzard.bounds + hazzard_pos.curr));
+                    break
+                }
+            }
+            let (hazzard_entity, hazzard_bounds) = match hit {
+                Some(hit) => hit,
+                None => continue
+            };
+            if self.entities.get::<RemoveOnImpact>(hazzard_entity).is_ok() {
+                to_despawn.push(hazzard_entity);
+            }
+            match health {
+                Some(health) if health.hits > 1 => {
+                    health.hits -= 1;
+                    health.invulnerable = invulnerability_time;
+                    if let Some(physics) = physics {
+                        // Away from the hazzard, and always a bit upwards so we don't get pushed into the floor
+                        let away = bounds.center() - hazzard_bounds.center();
+                        let away = if away.len() > 0.0 { away.norm() } else { Vec2(0.0, 1.0) };
+                        physics.vel = (away + Vec2(0.0, 0.5)).norm() * knockback_speed;
+                    }
+                },
+                health => {
+                    if let Some(health) = health {
+                        health.hits = 0;
+                    }
+                    deaths.push(bounds.center());
                     if killable.loss_on_death {
                         loss = Some(pos.curr);
                     } else {
-                        todo!();
+                        to_despawn.push(entity);
+                        if self.entities.get::<Cary>(entity).is_ok() {
+                            walker_died = Some(pos.curr);
+                        }
                     }
                 }
             }
         }
+        to_despawn.sort();
+        to_despawn.dedup();
+        for entity in to_despawn {
+            self.despawn(entity);
+        }
+        // Without anyone left to reach the exit, the level can't be won anymore
+        if let (None, Some(pos), true) = (loss, walker_died, self.walkers().is_empty()) {
+            loss = Some(pos);
+        }
+        for pos in deaths {
+            self.particles.emit(&particles::DEBRIS, pos, Vec2(0.0, 1.0));
+            self.sounds.push(Sound::Death);
+        }
         if let Some(pos) = loss {
             self.state = WorldState::Loss(pos, 0.0)
         }
     }
 
+    fn update_invulnerability(&mut self) {
+        for (_, health) in self.query::<&mut Health>().iter() {
+            health.invulnerable = (health.invulnerable - TIME_BETWEEN_UPDATES).max(0.0);
+        }
+    }
+
     fn update_shooters(&mut self) {
-        let max_cooldown = 3.0;
-        let player_pos = self.entities.get::<Pos>(self.player).unwrap().curr;
+        const BURST_INTERVAL: f32 = 0.2;
         let mut entities_to_spawn = Vec::new(); // TODO: don't allocate each frame
-        for (_, (pos, shooter)) in self.query::<(&Pos, &mut Shooter)>().iter() {
-            shooter.cooldown -= TIME_BETWEEN_UPDATES;
-            if shooter.cooldown <= 0.0 {
-                shooter.cooldown = max_cooldown;
-                entities_to_spawn.push(make_bullet(pos.curr + Vec2(0.0, 0.5), player_pos));
+        for (entity, (pos, shooter)) in self.query::<(&Pos, &mut Shooter)>().iter() {
+            // Fire as soon as a target comes into sight
+            shooter.cooldown = (shooter.cooldown - TIME_BETWEEN_UPDATES).max(0.0);
+            if shooter.cooldown > 0.0 {
+                continue
+            }
+            let muzzle = pos.curr + Vec2(0.0, 0.5);
+            let direction = if let Aim::Fixed(rotation) = shooter.aim {
+                // Fires on every cooldown, whether a target is in sight or not
+                Vec2(0.0, 1.0).rotated(rotation)
+            } else {
+                let (target_pos, target_vel) = match self.shooter_target(entity, muzzle, shooter) {
+                    Some(target) => target,
+                    None => {
+                        shooter.burst_left = 0;
+                        continue
+                    }
+                };
+                match shooter.aim {
+                    Aim::Lead => {
+                        // Where the target will be when the bullet arrives, which changes the flight time, so repeat
+                        let mut aim = target_pos;
+                        for _ in 0..3 {
+                            aim = target_pos + target_vel * (aim.dist(muzzle) / shooter.bullet_speed);
+                        }
+                        aim - muzzle
+                    },
+                    _ => target_pos - muzzle
+                }
+            };
+            if direction.len() == 0.0 {
+                continue
+            }
+            entities_to_spawn.push(make_bullet(muzzle, direction.norm() * shooter.bullet_speed, shooter.knockback));
+            shooter.cooldown = shooter.max_cooldown;
+            if let Aim::Burst(shots) = shooter.aim {
+                shooter.burst_left = if shooter.burst_left == 0 { shots - 1 } else { shooter.burst_left - 1 };
+                if shooter.burst_left > 0 {
+                    shooter.cooldown = BURST_INTERVAL;
+                }
             }
         }
         for entity in entities_to_spawn {
-            self.entities.spawn(entity);
+            let entity = self.entities.spawn(entity);
+            self.index(entity);
+            self.sounds.push(Sound::Shoot);
         }
     }
 
+    /// Position and velocity of the closest target the shooter can see
+    fn shooter_target(&self, shooter_entity: Entity, muzzle: Vec2, shooter: &Shooter) -> Option<(Vec2, Vec2)> {
+        let player = Some(self.player).filter(|_|shooter.target != ShooterTarget::Cary);
+        let mut walkers = self.query::<&Cary>();
+        let walkers = walkers.iter()
+            .map(|(entity, _)|entity)
+            .filter(|_|shooter.target != ShooterTarget::Player);
+        player.into_iter().chain(walkers)
+            .filter_map(|entity| {
+                let mut query = self.entities.query_one::<(&Pos, &Physics)>(entity).ok()?;
+                let (pos, physics) = query.get()?;
+                Some(((physics.bounds + pos.curr).center(), physics.vel))
+            })
+            .filter(|(pos, _)|(pos.dist(muzzle) <= shooter.range) && self.in_sight(muzzle, *pos, shooter_entity))
+            .min_by(|a, b|a.0.dist(muzzle).partial_cmp(&b.0.dist(muzzle)).unwrap_or(std::cmp::Ordering::Equal))
+    }
+
+    /// Whether there is no collider between the two points, except the ignored one
+    fn in_sight(&self, from: Vec2, to: Vec2, ignore: Entity) -> bool {
+        let area = Bounds {
+            min: Vec2(from.0.min(to.0), from.1.min(to.1)),
+            max: Vec2(from.0.max(to.0), from.1.max(to.1))
+        };
+        let ray = Bounds { min: from, max: from };
+        self.colliders.query(area).into_iter().all(|entity| {
+            if (entity == ignore) | matches!(self.entities.get::<Carryable>(entity), Ok(carryable) if carryable.carried) {
+                return true
+            }
+            let mut query = self.entities.query_one::<(&Pos, &Collider)>(entity).unwrap();
+            match query.get() {
+                Some((pos, collider)) => ray.sweep(to - from, collider.bounds + pos.curr).is_none(),
+                None => true
+            }
+        })
+    }
+
     fn update_remove_on_impact(&mut self) {
         let mut entities_to_despawn = Vec::new(); // TODO: don't allocate each frame
-        for (entity, (_, physics)) in self.query::<(&RemoveOnImpact, &Physics)>().iter() {
+        for (entity, (remove, pos, physics)) in self.query::<(&RemoveOnImpact, &Pos, &Physics)>().iter() {
             if physics.collided != (Horizontal::None, Vertical::None) {
-                entities_to_despawn.push(entity)
+                // Whatever was hit is just behind the surface
+                let knockback = match physics.contact_normal {
+                    Some(normal) if remove.knockback > 0.0 => 
+                        Some((physics.bounds + pos.curr + normal * (-2.0 * CONTACT_SKIN), normal * -remove.knockback)),
+                    _ => None
+                };
+                let normal = physics.contact_normal.unwrap_or(Vec2(0.0, 1.0));
+                entities_to_despawn.push((entity, pos.curr, normal, knockback))
+            }
+        }
+        for (entity, pos, normal, knockback) in entities_to_despawn {
+            if let Some((bounds, impulse)) = knockback {
+                self.knock_back(bounds, impulse);
+            }
+            self.particles.emit(&particles::SPARKS, pos, normal);
+            self.sounds.push(Sound::Impact);
+            self.despawn(entity);
+        }
+    }
+
+    /// Speeds up the movable blocks overlapping the bounds, or those carrying them
+    fn knock_back(&mut self, bounds: Bounds, impulse: Vec2) {
+        for entity in self.colliders.query(bounds) {
+            if self.entities.get::<Pushable>(entity).is_err() {
+                continue
+            }
+            let hit = {
+                let mut query = self.entities.query_one::<(&Pos, &Collider)>(entity).unwrap();
+                matches!(query.get(), Some((pos, collider)) if (collider.bounds + pos.curr).overlapps(bounds))
+            };
+            if !hit {
+                continue
+            }
+            let body = match self.entities.get::<ChildOf>(entity) {
+                Ok(child_of) => child_of.parent,
+                Err(_) => entity
+            };
+            if let Ok(mut physics) = self.entities.get_mut::<Physics>(body) {
+                physics.vel += impulse;
             }
         }
-        for entity in entities_to_despawn {
-            self.entities.despawn(entity);
+    }
+
+    fn update_checkpoints(&mut self) {
+        let mut reached = None;
+        for (_, (walker_pos, carryable, _)) in self.query::<(&Pos, &Carryable, &Cary)>().iter() {
+            if carryable.carried {
+                continue
+            }
+            for (entity, (pos, checkpoint)) in self.query::<(&Pos, &Checkpoint)>().iter() {
+                if (checkpoint.0 + pos.curr).contains(walker_pos.curr) {
+                    reached = Some(entity);
+                }
+            }
+        }
+        let checkpoint = match reached {
+            Some(checkpoint) => checkpoint,
+            None => return
+        };
+        if let Some(snapshot) = &self.snapshot {
+            if snapshot.checkpoint == checkpoint {
+                return
+            }
+            self.entities.get_mut::<Sprite>(snapshot.checkpoint).unwrap().tex = &textures::CHECKPOINT[..1];
+        }
+        self.entities.get_mut::<Sprite>(checkpoint).unwrap().tex = &textures::CHECKPOINT[1..];
+
+        // Rewinding respawns entities with new ids, restore() needs those of the freshly loaded level
+        let original = |entity|self.rewind.original(entity);
+        let mut positions = vec![(self.player, self.entities.get::<Pos>(self.player).unwrap().curr)];
+        for (entity, (pos, _)) in self.query::<(&Pos, &Pushable)>().iter() {
+            positions.push((original(entity), pos.curr));
+        }
+        let paths = self.query::<(&Pos, &PathFollower)>().iter()
+            .map(|(entity, (pos, path))|(original(entity), pos.curr, path.clone()))
+            .collect();
+        let walkers = self.query::<(&Pos, &Cary)>().iter()
+            .map(|(entity, (pos, cary))|(original(entity), pos.curr, cary.walk_right))
+            .collect();
+        self.snapshot = Some(Snapshot {
+            checkpoint,
+            time: self.time,
+            positions,
+            walkers,
+            paths
+        });
+    }
+
+    /// Moves everything to where it was at the checkpoint.
+    /// Only works on a freshly loaded world of the same level.
+    fn restore(&mut self, snapshot: &Snapshot) {
+        for &(entity, pos) in &snapshot.positions {
+            *self.entities.get_mut::<Pos>(entity).unwrap() = pos.into();
+            self.index(entity);
+        }
+        for (entity, pos, path) in &snapshot.paths {
+            *self.entities.get_mut::<Pos>(*entity).unwrap() = (*pos).into();
+            *self.entities.get_mut::<PathFollower>(*entity).unwrap() = path.clone();
+            self.index(*entity);
+        }
+        for walker in self.walkers() {
+            match snapshot.walkers.iter().find(|(entity, _, _)|*entity == walker) {
+                Some(&(_, pos, walk_right)) => {
+                    *self.entities.get_mut::<Pos>(walker).unwrap() = pos.into();
+                    self.entities.get_mut::<Cary>(walker).unwrap().walk_right = walk_right;
+                    self.entities.get_mut::<Sprite>(walker).unwrap().mirror = !walk_right;
+                    self.index(walker);
+                },
+                // Already reached an exit
+                None => self.despawn(walker)
+            }
         }
+        self.entities.get_mut::<Sprite>(snapshot.checkpoint).unwrap().tex = &textures::CHECKPOINT[1..];
+        self.camera.pos = self.entities.get::<Pos>(self.player).unwrap().curr;
+        self.time = snapshot.time;
+        self.snapshot = Some(snapshot.clone());
+        self.replayable = false;
     }
 
+    /// Walkers leave through the exits, the level is won once all of them did
     fn update_exits(&mut self) {
-        let cary_pos = self.entities.get::<Pos>(self.cary).unwrap().curr;
-        let mut reached = false;
-        for (_, (pos, exit)) in self.query::<(&Pos, &Exit)>().iter() {
-            if (exit.0 + pos.curr).contains(cary_pos) {
-                reached = true;
+        let mut exited = Vec::new();
+        for (walker, (walker_pos, _)) in self.query::<(&Pos, &Cary)>().iter() {
+            for (_, (pos, exit)) in self.query::<(&Pos, &Exit)>().iter() {
+                if (exit.0 + pos.curr).contains(walker_pos.curr) {
+                    exited.push((walker, walker_pos.curr));
+                    break
+                }
             }
         }
-        if reached {
-            self.state = WorldState::Victory(cary_pos, 0.0);
+        for &(walker, _) in &exited {
+            self.despawn(walker);
+        }
+        let needed = |walker: Entity|self.entities.get::<Killable>(walker).map_or(true, |killable|killable.loss_on_death);
+        if let (Some(&(_, last_pos)), false) = (exited.last(), self.walkers().into_iter().any(needed)) {
+            self.state = WorldState::Victory(last_pos, 0.0);
+            self.sounds.push(Sound::Victory);
         }
     }
 
@@ -563,17 +1600,27 @@ impl World {
 
     fn update_camera(&mut self) {
         let player_pos = self.entities.get::<Pos>(self.player).unwrap().curr;
-        let cary_pos = self.entities.get::<Pos>(self.cary).unwrap().curr;
+        // Follow the walker closest to the player
+        let cary_pos = self.query::<(&Pos, &Cary)>().iter()
+            .map(|(_, (pos, _))|pos.curr)
+            .min_by(|a, b|(*a - player_pos).len().partial_cmp(&(*b - player_pos).len()).unwrap())
+            .unwrap_or(player_pos);
 
         let max_x_diff = 1.55 * self.camera.size; // todo: make dependant on aspect ratio
         let x_diff = (cary_pos.0 - player_pos.0).max(-max_x_diff).min(max_x_diff);
         let max_y_diff = 1.25 * self.camera.size;
         let y_diff = (cary_pos.1 - player_pos.1).max(-max_y_diff).min(max_y_diff);
 
-        let target = Vec2(
+        let mut target = Vec2(
             player_pos.0 + 0.5 * x_diff,
             player_pos.1 + 0.5 * y_diff
         );
+        if let Some(bounds) = self.meta.camera_bounds {
+            target = Vec2(
+                target.0.max(bounds.min.0).min(bounds.max.0),
+                target.1.max(bounds.min.1).min(bounds.max.1)
+            );
+        }
 
         self.camera.pos += (target-self.camera.pos) * 1.5 * TIME_BETWEEN_UPDATES;
 
@@ -592,19 +1639,14 @@ impl World {
     }
 
     fn render(&self, renderer: &mut Renderer, lerp: f32) {
-        // Sprites
-        for (_, (pos, sprite)) in self.query::<(&Pos, &Sprite)>().iter() {
-            let pos = pos.prev_interpol.lerp(pos.curr, lerp) + sprite.offset;
-            let index_base = (sprite.timer / sprite.frame_duration) as usize;
-            let tex = &sprite.tex[
-                if sprite.repeat {
-                    index_base % sprite.tex.len()
-                } else {
-                    index_base.min(sprite.tex.len()-1)
-                }
-            ];
-            renderer.draw(&self.camera, pos, sprite.tex_anchor, tex, sprite.layer, sprite.mirror, sprite.rotation)
-        }
+        self.draw(renderer, lerp);
+        renderer.render()
+    }
+
+    /// Like render(), but doesn't present the frame so more can be drawn on top
+    fn draw(&self, renderer: &mut Renderer, lerp: f32) {
+        self.render_sprites(renderer, &self.camera, lerp);
+        self.particles.render(renderer, &self.camera, lerp);
 
         // Pickup hint
         if let Some(carryable) = self.find_pickupable() {
@@ -627,6 +1669,29 @@ impl World {
                 Layer::UI, false, 0);
         }
 
+        // Remaining hits, only shown when the player can take more than one
+        let health = self.entities.get::<Health>(self.player).unwrap();
+        if health.max_hits > 1 {
+            for hit in 0..health.hits {
+                renderer.draw(&UI_CAMERA, Vec2(-6.0 + hit as f32 * 0.8, 6.0), textures::TexAnchor::Center,
+                    &textures::PLAYER_IDLE[0], Layer::UI, false, 0);
+            }
+        }
+
+        // Timer, and the level name at the start
+        let hud_style = Style { align: Align::Center, color: text::BLACK, ..Style::default() };
+        if let WorldState::Running = self.state {
+            let timer = match self.meta.par_time {
+                Some(par_time) => format!("{:.1} / {:.0}", self.time, par_time),
+                None => format!("{:.1}", self.time)
+            };
+            text::draw(renderer, &UI_CAMERA, Vec2(0.0, 6.0), &timer, &hud_style);
+            let level_name_duration = 3.0;
+            if let (Some(name), true) = (&self.meta.name, self.time < level_name_duration) {
+                text::draw(renderer, &UI_CAMERA, Vec2(0.0, 5.2), name, &hud_style);
+            }
+        }
+
         // Transition
         let transition_speed = 1.3;
         match self.state {
@@ -645,29 +1710,160 @@ impl World {
                 if time > GAME_END_WAIT_TIME {
                     renderer.draw(&UI_CAMERA, Vec2::zero(), textures::TexAnchor::Center, 
                         &textures::TEXT_NEXT[0], Layer::UI, false, 0);
+                    text::draw(renderer, &UI_CAMERA, Vec2(0.0, -2.8), &format!("Time {:.2} s", self.time), &hud_style);
                 }
             }
         }
+    }
 
-        renderer.render()
+    fn render_sprites(&self, renderer: &mut Renderer, camera: &Camera, lerp: f32) {
+        for (_, (pos, sprite, health)) in self.query::<(&Pos, &Sprite, Option<&Health>)>().iter() {
+            // Blink while invulnerable
+            if let Some(health) = health {
+                if (health.invulnerable > 0.0) & ((health.invulnerable * 10.0) as i32 % 2 == 1) {
+                    continue
+                }
+            }
+            let pos = pos.prev_interpol.lerp(pos.curr, lerp) + sprite.offset;
+            let index_base = (sprite.timer / sprite.frame_duration) as usize;
+            let tex = &sprite.tex[
+                if sprite.repeat {
+                    index_base % sprite.tex.len()
+                } else {
+                    index_base.min(sprite.tex.len()-1)
+                }
+            ];
+            renderer.draw(camera, pos, sprite.tex_anchor, tex, sprite.layer, sprite.mirror, sprite.rotation)
+        }
     }
 }
 
-enum WorldState {
+/// Where the player, the walkers and the movable blocks were when a walker passed a checkpoint.
+/// Loading a level always spawns the same entity ids, so this can be restored into a new world.
+/// Entities that rewinding respawned are kept by their original ids.
+#[derive(Clone)]
+struct Snapshot {
+    checkpoint: Entity,
+    time: f32,
+    /// Player and movable blocks
+    positions: Vec<(Entity, Vec2)>,
+    /// Walkers which haven't reached an exit yet, and whether they walk right
+    walkers: Vec<(Entity, Vec2, bool)>,
+    /// Platforms and other moving entities
+    paths: Vec<(Entity, Vec2, PathFollower)>
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum WorldState {
     Running,
     Loss(Vec2, f32),
     Victory(Vec2, f32)
 }
 
-fn render_show_controls(renderer: &mut Renderer) {
+fn draw_pause_menu(renderer: &mut Renderer, selected: usize, audio: &Audio) {
+    for (index, option) in PAUSE_OPTIONS.iter().enumerate() {
+        let label = match option {
+            PauseOption::Resume => "Resume".to_string(),
+            PauseOption::Restart => "Restart level".to_string(),
+            PauseOption::Controls => "Controls".to_string(),
+            PauseOption::LevelSelect => "Level select".to_string(),
+            PauseOption::Volume => format!("Volume {}%", (audio.volume() * 100.0).round()),
+            PauseOption::Quit => "Quit".to_string()
+        };
+        let pos = Vec2(0.0, ((PAUSE_OPTIONS.len() - 1) as f32 / 2.0 - index as f32) * 1.2);
+        let style = Style {
+            align: Align::Center,
+            color: if index == selected { text::HIGHLIGHT } else { WHITE },
+            ..Style::default()
+        };
+        text::draw(renderer, &UI_CAMERA, pos, &label, &style);
+        if index == selected {
+            let pos = pos - Vec2(text::width(&label) / 2.0 + 0.5, 0.0);
+            text::draw(renderer, &UI_CAMERA, pos, ">", &Style { align: Align::Right, ..style });
+        }
+    }
+}
+
+/// Shows the key bindings, as menu if an action is selected.
+/// The bool is true while waiting for the key to bind.
+fn render_controls(renderer: &mut Renderer, input: &Input, menu: Option<(usize, bool)>) {
     for x in -30..31 {
         for y in -10..10 {
             renderer.draw(&UI_CAMERA, Vec2(x as f32, y as f32), textures::TexAnchor::Center, 
                 &textures::CYAN[0], Layer::ForegroundTile, false, 0);
         }
     }
-    renderer.draw(&UI_CAMERA, Vec2::zero(), textures::TexAnchor::Center, 
-        &textures::CONTROLS[0], Layer::UI, false, 0);
+    for (index, action) in ACTIONS.iter().enumerate() {
+        let y = 5.0 - index as f32;
+        let selected = matches!(menu, Some((selected, _)) if selected == index);
+        let style = Style {
+            color: if selected { text::HIGHLIGHT } else { WHITE },
+            ..Style::default()
+        };
+        text::draw(renderer, &UI_CAMERA, Vec2(-6.0, y), action.label(), &style);
+        let keys = match menu {
+            Some((selected, true)) if selected == index => "Press a key".to_string(),
+            _ => input.key_names(*action).join(", ")
+        };
+        text::draw(renderer, &UI_CAMERA, Vec2(-1.0, y), &keys, &style);
+        if selected {
+            text::draw(renderer, &UI_CAMERA, Vec2(-6.5, y), ">", &Style { align: Align::Right, ..style });
+        }
+    }
+    text::draw(renderer, &UI_CAMERA, Vec2(-6.0, -3.5),
+        "Gamepad: Stick or D-pad to move, A to flap, B to dive, X to pick up, Y to rewind, Start to pause",
+        &Style { max_width: Some(13.0), ..Style::default() });
+
+    let key_name = |action|input.key_names(action).first().copied().unwrap_or("?");
+    let hint = if menu.is_some() {
+        format!("{}: Change   {}: Back", key_name(Action::Confirm), key_name(Action::Pause))
+    } else {
+        format!("{}: Start   {}: Change controls", key_name(Action::Confirm), key_name(Action::Pause))
+    };
+    text::draw(renderer, &UI_CAMERA, Vec2(-6.0, -6.0), &hint, &Style::default());
+    renderer.render();
+}
+
+const LEVEL_SELECT_COLUMNS: usize = 8;
+
+fn render_level_select(renderer: &mut Renderer, levels: &Levels, progress: &Progress) {
+    for x in -30..31 {
+        for y in -10..10 {
+            renderer.draw(&UI_CAMERA, Vec2(x as f32, y as f32), textures::TexAnchor::Center, 
+                &textures::CYAN[0], Layer::Background, false, 0);
+        }
+    }
+    let spacing = 2.0;
+    for (index, level_string) in levels.level_strings.iter().enumerate() {
+        let pos = Vec2(
+            ((index % LEVEL_SELECT_COLUMNS) as f32 - (LEVEL_SELECT_COLUMNS - 1) as f32 / 2.0) * spacing,
+            4.0 - (index / LEVEL_SELECT_COLUMNS) as f32 * spacing
+        );
+        renderer.draw(&UI_CAMERA, pos, textures::TexAnchor::Top, 
+            &textures::TILE_SOLID[0], Layer::ForegroundTile, false, 0);
+        if progress.get(level_string).completed {
+            renderer.draw(&UI_CAMERA, pos, textures::TexAnchor::Bottom, 
+                &textures::EXIT[0], Layer::Foreground, false, 0);
+        }
+        if index == levels.index() {
+            renderer.draw(&UI_CAMERA, pos + Vec2(0.0, 0.5), textures::TexAnchor::Center, 
+                &textures::PLAYER_IDLE[0], Layer::ForegroundPlayer, false, 0);
+        }
+    }
+
+    let level_progress = progress.get(levels.current());
+    let mut details = format!("Level {}", levels.index() + 1);
+    if let Some(name) = levels.name() {
+        details += &format!(": {}", name);
+    }
+    if let Some(best_time) = level_progress.best_time {
+        details += &format!("\nBest time {:.2} s", best_time);
+    }
+    if level_progress.deaths > 0 {
+        details += &format!("\nDeaths {}", level_progress.deaths);
+    }
+    text::draw(renderer, &UI_CAMERA, Vec2(0.0, -4.0), &details, &Style { align: Align::Center, ..Style::default() });
+    renderer.set_transition(&UI_CAMERA, Vec2::zero(), 0.0, false);
     renderer.render();
 }
 
@@ -681,4 +1877,42 @@ fn render_victory(renderer: &mut Renderer) {
     renderer.draw(&UI_CAMERA, Vec2::zero(), textures::TexAnchor::Center, 
         &textures::VICTORY[0], Layer::UI, false, 0);
     renderer.render();
-}
\ No newline at end of file
+}
+
+fn render_level_error(renderer: &mut Renderer, input: &Input, message: &str) {
+    for x in -30..31 {
+        for y in -10..10 {
+            renderer.draw(&UI_CAMERA, Vec2(x as f32, y as f32), textures::TexAnchor::Center, 
+                &textures::RED[0], Layer::ForegroundTile, false, 0);
+        }
+    }
+    let style = Style { align: Align::Center, max_width: Some(16.0), ..Style::default() };
+    let message = format!("Failed to load level\n{}", message);
+    // Centered vertically as a whole
+    let top = text::height(&message, style.max_width) / 2.0;
+    text::draw(renderer, &UI_CAMERA, Vec2(0.0, top), &message, &style);
+    let key_name = input.key_names(Action::Confirm).first().copied().unwrap_or("?");
+    text::draw(renderer, &UI_CAMERA, Vec2(0.0, -6.0), &format!("{}: Skip level", key_name), &style);
+    renderer.set_transition(&UI_CAMERA, Vec2::zero(), 0.0, false);
+    renderer.render();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_aim_without_target() {
+        // The shooter is walled in below the floor, out of sight of everyone
+        let shots = |aim| {
+            let mut world = level::load(&format!("[level]\nversion = 2\n[tiles]\n#P    #\n#  C E#\n#######\n#  S  #\n#######\n\
+                [entities]\n3 3 aim={} delay=0.5\n", aim)).unwrap();
+            for _ in 0..60 {
+                world.update(Controllable::default());
+            }
+            world.sounds.iter().filter(|sound|**sound == Sound::Shoot).count()
+        };
+        assert!(shots("fixed") > 0);
+        assert_eq!(shots("direct"), 0);
+    }
+}
@@ -127,6 +127,19 @@ impl std::ops::AddAssign<Vec2> for Bounds {
 
 
 
+/// Bodies are kept this far away from the surfaces they hit,
+/// so rounding errors don't make them overlap on the next update
+pub const CONTACT_SKIN: f32 = 0.001;
+
+/// Where a moving box first touches another
+#[derive(Debug, Copy, Clone)]
+pub struct Hit {
+    /// Fraction of the movement until the impact
+    pub time: f32,
+    /// Points away from the surface that was hit
+    pub normal: Vec2
+}
+
 /// Axis-aligned bounding box
 #[derive(Debug, Copy, Clone)]
 pub struct Bounds {
@@ -160,6 +173,35 @@ impl Bounds {
         (pos.0  > self.min.0) & (pos.0 < self.max.0) & (pos.1 > self.min.1) & (pos.1 < self.max.1)
     }
 
+    /// Swept AABB test: when moving by movement, where does this box first hit the obstacle?
+    /// If both axes are hit at the same time (a corner), the vertical one counts.
+    pub fn sweep(self, movement: Vec2, obstacle: Bounds) -> Option<Hit> {
+        let (entry_x, exit_x) = sweep_axis(self.min.0, self.max.0, obstacle.min.0, obstacle.max.0, movement.0);
+        let (entry_y, exit_y) = sweep_axis(self.min.1, self.max.1, obstacle.min.1, obstacle.max.1, movement.1);
+        let (entry, normal, speed) = if entry_x > entry_y {
+            (entry_x, Vec2(-movement.0.signum(), 0.0), movement.0.abs())
+        } else {
+            (entry_y, Vec2(0.0, -movement.1.signum()), movement.1.abs())
+        };
+        let exit = exit_x.min(exit_y);
+        if (entry >= exit) | (entry > 1.0) | (exit <= 0.0) {
+            return None
+        }
+        if entry < 0.0 {
+            // Already overlapping: only a hit if they are just touching, 
+            // otherwise the body shouldn't be trapped inside.
+            // NaN if overlapping on an axis without movement.
+            let depth = -entry * speed;
+            if depth.is_nan() | (depth > CONTACT_SKIN) {
+                return None
+            }
+        }
+        Some(Hit {
+            time: entry.max(0.0),
+            normal
+        })
+    }
+
     /*
     pub fn check_move_against(self, movement: Vec2, obstacle: Bounds) -> Vec2 {
         let eps = 0.0005;
@@ -225,4 +267,79 @@ impl Bounds {
         }
     }
     */
-}
\ No newline at end of file
+}
+
+/// Times as fraction of the movement at which the intervals start and stop overlapping
+fn sweep_axis(min: f32, max: f32, obstacle_min: f32, obstacle_max: f32, movement: f32) -> (f32, f32) {
+    if movement > 0.0 {
+        ((obstacle_min - max) / movement, (obstacle_max - min) / movement)
+    } else if movement < 0.0 {
+        ((obstacle_max - min) / movement, (obstacle_min - max) / movement)
+    } else if (max - CONTACT_SKIN / 2.0 > obstacle_min) & (min + CONTACT_SKIN / 2.0 < obstacle_max) {
+        (f32::NEG_INFINITY, f32::INFINITY)
+    } else {
+        (f32::INFINITY, f32::NEG_INFINITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box(x: f32, y: f32) -> Bounds {
+        Bounds { min: Vec2(x, y), max: Vec2(x + 1.0, y + 1.0) }
+    }
+
+    fn hit(bounds: Bounds, movement: Vec2, obstacle: Bounds) -> (f32, f32, f32) {
+        match bounds.sweep(movement, obstacle) {
+            Some(hit) => (hit.time, hit.normal.0, hit.normal.1),
+            None => panic!("{:?} moving by {:?} missed {:?}", bounds, movement, obstacle)
+        }
+    }
+
+    #[test]
+    fn sweep_axis_times() {
+        assert_eq!(sweep_axis(0.0, 1.0, 2.0, 3.0, 2.0), (0.5, 1.5));
+        assert_eq!(sweep_axis(2.0, 3.0, 0.0, 1.0, -2.0), (0.5, 1.5));
+        assert_eq!(sweep_axis(0.0, 1.0, 0.5, 3.0, 0.0), (f32::NEG_INFINITY, f32::INFINITY));
+        assert_eq!(sweep_axis(0.0, 1.0, 2.0, 3.0, 0.0), (f32::INFINITY, f32::NEG_INFINITY));
+        // Without movement, just touching within the skin doesn't count as overlapping
+        assert_eq!(sweep_axis(0.0, 1.0, 1.0 - CONTACT_SKIN / 4.0, 2.0, 0.0), (f32::INFINITY, f32::NEG_INFINITY));
+    }
+
+    #[test]
+    fn sweep_hits() {
+        assert_eq!(hit(unit_box(0.0, 0.0), Vec2(2.0, 0.0), unit_box(2.0, 0.0)), (0.5, -1.0, 0.0));
+        assert_eq!(hit(unit_box(0.0, 2.0), Vec2(0.0, -4.0), unit_box(0.0, 0.0)), (0.25, 0.0, 1.0));
+        assert!(unit_box(0.0, 0.0).sweep(Vec2(0.5, 0.0), unit_box(2.0, 0.0)).is_none());
+        assert!(unit_box(0.0, 0.0).sweep(Vec2(-2.0, 0.0), unit_box(2.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn sweep_corner_is_vertical() {
+        assert_eq!(hit(unit_box(0.0, 0.0), Vec2(2.0, 2.0), unit_box(2.0, 2.0)), (0.5, 0.0, -1.0));
+        assert_eq!(hit(unit_box(2.0, 2.0), Vec2(-2.0, -2.0), unit_box(0.0, 0.0)), (0.5, 0.0, 1.0));
+    }
+
+    #[test]
+    fn sweep_without_movement() {
+        assert!(unit_box(0.0, 0.0).sweep(Vec2::zero(), unit_box(2.0, 0.0)).is_none());
+        // Overlapping on both axes without movement is NaN deep, which isn't a hit
+        assert!(unit_box(0.0, 0.0).sweep(Vec2::zero(), unit_box(0.5, 0.5)).is_none());
+        // Falling next to a wall doesn't catch on it
+        assert!(unit_box(0.0, 2.0).sweep(Vec2(0.0, -4.0), unit_box(1.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn sweep_touching() {
+        let ground = Bounds { min: Vec2(-5.0, -1.0), max: Vec2(5.0, 0.0) };
+        assert_eq!(hit(unit_box(0.0, 0.0), Vec2(0.0, -0.1), ground), (0.0, 0.0, 1.0));
+        // Sunk in by less than the skin still stands on it
+        assert_eq!(hit(unit_box(0.0, -CONTACT_SKIN / 2.0), Vec2(0.0, -0.1), ground), (0.0, 0.0, 1.0));
+        // Deeper is trapped inside, so it may leave
+        assert!(unit_box(0.0, -0.01).sweep(Vec2(0.0, -0.1), ground).is_none());
+        // Moving away from a surface it touches
+        assert!(unit_box(0.0, 0.0).sweep(Vec2(0.0, 0.1), ground).is_none());
+        assert!(unit_box(0.0, 0.0).sweep(Vec2(0.3, 0.0), ground).is_none());
+    }
+}
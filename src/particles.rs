@@ -0,0 +1,122 @@
+use crate::{Camera, TIME_BETWEEN_UPDATES};
+use crate::math::*;
+use crate::renderer::{Renderer, Layer};
+use crate::textures::{self, TexCoords, TexAnchor};
+
+/// How a burst of particles looks and moves
+pub struct Emitter {
+    /// Played once over the lifetime of each particle
+    pub tex: &'static [TexCoords],
+    pub count: usize,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    /// Radians to either side of the direction
+    pub spread: f32,
+    pub lifetime: f32,
+    pub gravity: f32
+}
+
+/// Bullets hitting something
+pub const SPARKS: Emitter = Emitter {
+    tex: textures::SPARK,
+    count: 6,
+    min_speed: 1.5,
+    max_speed: 4.0,
+    spread: 1.2,
+    lifetime: 0.3,
+    gravity: 0.0
+};
+
+/// Something killed by a hazzard
+pub const DEBRIS: Emitter = Emitter {
+    tex: textures::DEBRIS,
+    count: 14,
+    min_speed: 2.0,
+    max_speed: 5.0,
+    spread: std::f32::consts::PI,
+    lifetime: 0.8,
+    gravity: 10.0
+};
+
+/// Flaps and blocks landing
+pub const DUST: Emitter = Emitter {
+    tex: textures::DUST,
+    count: 4,
+    min_speed: 0.5,
+    max_speed: 1.5,
+    spread: 1.4,
+    lifetime: 0.4,
+    gravity: 0.0
+};
+
+struct Particle {
+    pos: Vec2,
+    prev_interpol: Vec2,
+    vel: Vec2,
+    gravity: f32,
+    age: f32,
+    lifetime: f32,
+    tex: &'static [TexCoords]
+}
+
+/// Effects that don't interact with anything, so they aren't entities
+pub struct Particles {
+    particles: Vec<Particle>,
+    seed: u32
+}
+
+impl Default for Particles {
+    fn default() -> Self {
+        Particles {
+            particles: Vec::new(),
+            seed: 0x9E37_79B9
+        }
+    }
+}
+
+impl Particles {
+    pub fn emit(&mut self, emitter: &Emitter, pos: Vec2, direction: Vec2) {
+        let angle = direction.1.atan2(direction.0);
+        for _ in 0..emitter.count {
+            let angle = angle + (self.random() * 2.0 - 1.0) * emitter.spread;
+            let speed = emitter.min_speed + self.random() * (emitter.max_speed - emitter.min_speed);
+            // Don't all vanish at once
+            let lifetime = emitter.lifetime * (0.7 + 0.3 * self.random());
+            self.particles.push(Particle {
+                pos,
+                prev_interpol: pos,
+                vel: Vec2(angle.cos(), angle.sin()) * speed,
+                gravity: emitter.gravity,
+                age: 0.0,
+                lifetime,
+                tex: emitter.tex
+            });
+        }
+    }
+
+    pub fn update(&mut self) {
+        for particle in &mut self.particles {
+            particle.prev_interpol = particle.pos;
+            particle.vel.1 -= particle.gravity * TIME_BETWEEN_UPDATES;
+            particle.pos += particle.vel * TIME_BETWEEN_UPDATES;
+            particle.age += TIME_BETWEEN_UPDATES;
+        }
+        self.particles.retain(|particle|particle.age < particle.lifetime);
+    }
+
+    pub fn render(&self, renderer: &mut Renderer, camera: &Camera, lerp: f32) {
+        for particle in &self.particles {
+            let frame = (particle.age / particle.lifetime * particle.tex.len() as f32) as usize;
+            renderer.draw(camera, particle.prev_interpol.lerp(particle.pos, lerp), TexAnchor::Center,
+                &particle.tex[frame.min(particle.tex.len() - 1)], Layer::Foreground, false, 0);
+        }
+    }
+
+    /// Xorshift, so the effects are the same every time, e.g. in replays
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f32 / u32::MAX as f32
+    }
+}
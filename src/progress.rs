@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use crate::storage;
+
+/// File name on native, localStorage key on the web
+const SAVE_NAME: &str = "cary_progress";
+
+/// Ids of default levels from before they were changed, with what they became,
+/// so players keep their progress
+const MIGRATIONS: &[(u64, &str)] = &[
+    // Checkpoint added
+    (0xf53dad6d90b04db5, include_str!("../levels/level_5")),
+];
+
+#[derive(Default, Debug, Copy, Clone)]
+pub struct LevelProgress {
+    pub completed: bool,
+    /// In seconds
+    pub best_time: Option<f32>,
+    pub deaths: u32
+}
+
+/// Progress of every level that has been played, persisted between sessions
+#[derive(Default)]
+pub struct Progress {
+    levels: HashMap<u64, LevelProgress>
+}
+
+impl Progress {
+    pub fn load() -> Self {
+        Self::parse(&storage::read(SAVE_NAME).unwrap_or_default())
+    }
+
+    fn parse(save: &str) -> Self {
+        let mut progress = Progress::default();
+        // Format: one "<level id> <completed> <best time or -> <deaths>" per line
+        for line in save.lines() {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            if let [id, completed, best_time, deaths] = fields.as_slice() {
+                if let (Ok(id), Ok(deaths)) = (u64::from_str_radix(id, 16), deaths.parse()) {
+                    progress.levels.insert(id, LevelProgress {
+                        completed: *completed == "1",
+                        best_time: best_time.parse().ok(),
+                        deaths
+                    });
+                }
+            }
+        }
+        for &(old_id, level_string) in MIGRATIONS {
+            if let Some(old) = progress.levels.remove(&old_id) {
+                let level = progress.levels.entry(level_id(level_string)).or_default();
+                level.completed |= old.completed;
+                level.best_time = match (level.best_time, old.best_time) {
+                    (Some(time), Some(old_time)) => Some(time.min(old_time)),
+                    (time, old_time) => time.or(old_time)
+                };
+                level.deaths += old.deaths;
+            }
+        }
+        progress
+    }
+
+    pub fn save(&self) {
+        let mut string = String::new();
+        for (id, level) in &self.levels {
+            string.push_str(&format!("{:016x} {} {} {}\n",
+                id,
+                if level.completed {1} else {0},
+                level.best_time.map_or("-".to_string(), |time|time.to_string()),
+                level.deaths));
+        }
+        storage::write(SAVE_NAME, &string);
+    }
+
+    pub fn get(&self, level_string: &str) -> LevelProgress {
+        self.levels.get(&level_id(level_string)).copied().unwrap_or_default()
+    }
+
+    pub fn complete(&mut self, level_string: &str, time: f32) {
+        let level = self.levels.entry(level_id(level_string)).or_default();
+        level.completed = true;
+        level.best_time = Some(level.best_time.map_or(time, |best_time|best_time.min(time)));
+    }
+
+    pub fn death(&mut self, level_string: &str) {
+        self.levels.entry(level_id(level_string)).or_default().deaths += 1;
+    }
+}
+
+/// Levels are identified by their content, so that custom levels
+/// don't share progress with the default ones (FNV-1a hash)
+fn level_id(level_string: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in level_string.bytes().filter(|byte|*byte != b'\r') {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration() {
+        let level_5 = include_str!("../levels/level_5");
+        let progress = Progress::parse("f53dad6d90b04db5 1 42.5 3\n");
+        let level = progress.get(level_5);
+        assert!(level.completed);
+        assert_eq!(level.best_time, Some(42.5));
+        assert_eq!(level.deaths, 3);
+        assert!(!progress.levels.contains_key(&0xf53dad6d90b04db5));
+
+        // Merged with progress in the changed level
+        let save = format!("f53dad6d90b04db5 1 42.5 3\n{:016x} 0 - 2\n", level_id(level_5));
+        let level = Progress::parse(&save).get(level_5);
+        assert!(level.completed);
+        assert_eq!(level.best_time, Some(42.5));
+        assert_eq!(level.deaths, 5);
+    }
+}
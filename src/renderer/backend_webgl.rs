@@ -11,7 +11,7 @@ use web_sys::{
 use image::GenericImageView;
 use crate::textures::{self, TexCoords, TexAnchor};
 use crate::Vec2;
-use super::Layer;
+use super::{Layer, Color, WHITE};
 
 const ATTRIB_VERTEX: u32 = 0;
 const ATTRIB_POSITION: u32 = 1;
@@ -20,6 +20,7 @@ const ATTRIB_UV_CENTER: u32 = 3;
 const ATTRIB_UV_SIZE: u32 = 4;
 const ATTRIB_LAYER: u32 = 5;
 const ATTRIB_ROTATION: u32 = 6;
+const ATTRIB_TINT: u32 = 7;
 
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -30,7 +31,8 @@ struct SpriteInstance {
     uv_center: Vec2,
     uv_size: Vec2,
     layer: f32,
-    rotation: f32
+    rotation: f32,
+    tint: Color
 } 
 unsafe impl bytemuck::Pod for SpriteInstance {}
 unsafe impl bytemuck::Zeroable for SpriteInstance {}
@@ -131,6 +133,7 @@ impl Renderer {
         context.vertex_attrib_pointer_with_i32(ATTRIB_UV_SIZE,   2, WebGl2RenderingContext::FLOAT, false, instance_size, 4 * 8);
         context.vertex_attrib_pointer_with_i32(ATTRIB_LAYER,     1, WebGl2RenderingContext::FLOAT, false, instance_size, 4 * 10);
         context.vertex_attrib_pointer_with_i32(ATTRIB_ROTATION,  1, WebGl2RenderingContext::FLOAT, false, instance_size, 4 * 11);
+        context.vertex_attrib_pointer_with_i32(ATTRIB_TINT,      3, WebGl2RenderingContext::FLOAT, false, instance_size, 4 * 12);
         context.enable_vertex_attrib_array(ATTRIB_VERTEX);
         context.enable_vertex_attrib_array(ATTRIB_POSITION);
         context.enable_vertex_attrib_array(ATTRIB_SIZE);
@@ -138,6 +141,7 @@ impl Renderer {
         context.enable_vertex_attrib_array(ATTRIB_UV_SIZE);
         context.enable_vertex_attrib_array(ATTRIB_LAYER);
         context.enable_vertex_attrib_array(ATTRIB_ROTATION);
+        context.enable_vertex_attrib_array(ATTRIB_TINT);
 
 
         // Remove the "Loading..." text
@@ -233,11 +237,21 @@ impl Renderer {
                     uv_center: tex.center * textures::UV_COORDS_FACTOR,
                     uv_size: tex.size * if mirror {Vec2(-1.0, 1.0)} else {Vec2(1.0, 1.0)} * textures::UV_COORDS_FACTOR,
                     layer: layer.into(),
-                    rotation: rotation as f32
+                    rotation: rotation as f32,
+                    tint: WHITE
                 });
             }
         }
     }
+
+    /// Like draw(), but multiplies the texture with the color
+    pub fn draw_tinted(&mut self, camera: &crate::Camera, pos: Vec2, anchor: TexAnchor, tex: &TexCoords, layer: Layer, tint: Color) {
+        let first = self.sprite_instances.len();
+        self.draw(camera, pos, anchor, tex, layer, false, 0);
+        for instance in &mut self.sprite_instances[first..] {
+            instance.tint = tint;
+        }
+    }
 }
 
 #[wasm_bindgen::prelude::wasm_bindgen]
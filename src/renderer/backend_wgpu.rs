@@ -7,7 +7,7 @@ use winit::window::Window;
 use wgpu::*;
 use crate::textures::{self, TexCoords, TexAnchor};
 use crate::Vec2;
-use super::Layer;
+use super::{Layer, WHITE};
 
 
 // TODO: remove light stuffs
@@ -21,7 +21,8 @@ struct SpriteInstance {
     uv_center: Vec2,
     uv_size: Vec2,
     layer: f32,
-    rotation: f32
+    rotation: f32,
+    tint: super::Color
 } 
 unsafe impl bytemuck::Pod for SpriteInstance {}
 unsafe impl bytemuck::Zeroable for SpriteInstance {}
@@ -337,6 +338,12 @@ impl Renderer {
                                 offset: std::mem::size_of::<f32>() as BufferAddress * 9,
                                 shader_location: 6,
                                 format: VertexFormat::Float,
+                            },
+                            // Tint
+                            VertexAttributeDescriptor {
+                                offset: std::mem::size_of::<f32>() as BufferAddress * 10,
+                                shader_location: 7,
+                                format: VertexFormat::Float3,
                             }
                         ])
                     },
@@ -699,9 +706,19 @@ impl Renderer {
                 uv_center: tex.center * textures::UV_COORDS_FACTOR,
                 uv_size: tex.size * if mirror {Vec2(-1.0, 1.0)} else {Vec2(1.0, 1.0)} * textures::UV_COORDS_FACTOR,
                 layer: layer.into(),
-                rotation: rotation as f32
+                rotation: rotation as f32,
+                tint: WHITE
             })
         }
     }
 
+    /// Like draw(), but multiplies the texture with the color
+    pub fn draw_tinted(&mut self, camera: &crate::Camera, pos: Vec2, anchor: TexAnchor, tex: &TexCoords, layer: Layer, tint: super::Color) {
+        let first = self.sprite_instances.len();
+        self.draw(camera, pos, anchor, tex, layer, false, 0);
+        for instance in &mut self.sprite_instances[first..] {
+            instance.tint = tint;
+        }
+    }
+
 }
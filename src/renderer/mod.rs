@@ -24,6 +24,11 @@ pub enum Layer {
     BackgroundTile = 9,
 }
 
+/// Multiplied with the texture, so white parts take on the color
+pub type Color = (f32, f32, f32);
+
+pub const WHITE: Color = (1.0, 1.0, 1.0);
+
 impl From<Layer> for f32 {
     fn from(layer: Layer) -> Self {
         layer as i32 as f32 / 10.0
@@ -1,6 +1,7 @@
 #version 450
 
 layout(location = 0) in vec2 tex_coords;
+layout(location = 1) in vec3 tint;
 
 layout(location = 0) out vec4 out_color;
 
@@ -58,4 +59,5 @@ void main() {
     } else {
         out_color = color;
     }
+    out_color.rgb *= tint;
 }
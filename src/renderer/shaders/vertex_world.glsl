@@ -8,8 +8,10 @@ layout(location = 3) in vec2 uv_center;
 layout(location = 4) in vec2 uv_size;
 layout(location = 5) in float layer;
 layout(location = 6) in float rotation;
+layout(location = 7) in vec3 tint;
 
 layout(location = 0) out vec2 tex_coords_frag;
+layout(location = 1) out vec3 tint_frag;
 
 vec2 rotate(vec2 vert) {
     return rotation == 1 ? vec2(vert.y, -vert.x)
@@ -21,4 +23,5 @@ vec2 rotate(vec2 vert) {
 void main() {
     gl_Position = vec4(position + rotate(vertex * size), layer, 1.0);
     tex_coords_frag = uv_center + vec2(vertex.x, -vertex.y) * uv_size;
+    tint_frag = tint;
 }
@@ -2,6 +2,7 @@
 precision mediump float;
 
 in vec2 tex_coords;
+in vec3 tint_color;
 
 out vec4 out_color;
 
@@ -41,4 +42,5 @@ void main() {
     } else {
         out_color = color;
     }
+    out_color.rgb *= tint_color;
 }
\ No newline at end of file
@@ -9,8 +9,10 @@ layout(location = 3) in vec2 uv_center;
 layout(location = 4) in vec2 uv_size;
 layout(location = 5) in float layer;
 layout(location = 6) in float rotation;
+layout(location = 7) in vec3 tint;
 
 out vec2 tex_coords;
+out vec3 tint_color;
 
 
 vec2 rotate(vec2 vert) {
@@ -23,4 +25,5 @@ vec2 rotate(vec2 vert) {
 void main() {
     gl_Position = vec4(position + rotate(vertex * size), layer, 1.0);
     tex_coords = uv_center + vec2(vertex.x, -vertex.y) * uv_size;
+    tint_color = tint;
 }
\ No newline at end of file
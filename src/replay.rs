@@ -0,0 +1,194 @@
+use crate::{WorldState, Simulation, LevelError};
+use crate::components::*;
+
+/// Increment whenever the file layout changes
+const FORMAT_VERSION: u32 = 1;
+const MAGIC: &str = "cary-replay";
+const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The inputs of one attempt at a level, one per update
+pub struct Replay {
+    pub level_string: String,
+    pub inputs: Vec<Controllable>
+}
+
+impl Replay {
+    /// Layout:
+    /// ```text
+    /// cary-replay <format version> <game version>\n
+    /// <length of level string in bytes>\n
+    /// <level string>
+    /// (<input>, <repetitions>) byte pairs until the end of the file
+    /// ```
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = format!("{} {} {}\n{}\n", MAGIC, FORMAT_VERSION, GAME_VERSION, self.level_string.len())
+            .into_bytes();
+        bytes.extend_from_slice(self.level_string.as_bytes());
+
+        let mut inputs = self.inputs.iter().map(|control|encode_control(*control)).peekable();
+        while let Some(input) = inputs.next() {
+            let mut repetitions = 1u8;
+            while (repetitions < u8::MAX) & (inputs.peek() == Some(&input)) {
+                inputs.next();
+                repetitions += 1;
+            }
+            bytes.push(input);
+            bytes.push(repetitions);
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let (header, rest) = split_line(bytes).ok_or("Missing header")?;
+        let mut header = header.split(' ');
+        if header.next() != Some(MAGIC) {
+            return Err("Not a replay file".into())
+        }
+        match header.next().and_then(|version|version.parse::<u32>().ok()) {
+            Some(FORMAT_VERSION) => (),
+            Some(version) => return Err(format!("Unsupported replay format version {}", version)),
+            None => return Err("Missing replay format version".into())
+        }
+        match header.next() {
+            Some(GAME_VERSION) => (),
+            // Physics may have changed, but the replay might still work
+            Some(version) => println!("Replay was recorded with version {}, this is version {}", version, GAME_VERSION),
+            None => return Err("Missing game version".into())
+        }
+
+        let (level_len, rest) = split_line(rest).ok_or("Missing level length")?;
+        let level_len: usize = level_len.parse().map_err(|_|"Invalid level length")?;
+        if rest.len() < level_len {
+            return Err("Level string is truncated".into())
+        }
+        let level_string = std::str::from_utf8(&rest[..level_len])
+            .map_err(|_|"Level string is not valid UTF-8")?
+            .to_string();
+
+        let rest = &rest[level_len..];
+        if rest.len() % 2 != 0 {
+            return Err("Inputs are truncated".into())
+        }
+        let mut inputs = Vec::new();
+        for pair in rest.chunks(2) {
+            let control = decode_control(pair[0]).ok_or("Invalid input")?;
+            inputs.resize(inputs.len() + pair[1] as usize, control);
+        }
+
+        Ok(Replay {
+            level_string,
+            inputs
+        })
+    }
+
+    /// Plays back the replay without rendering and returns how it ended
+    pub fn simulate(&self) -> Result<WorldState, LevelError> {
+        Ok(Simulation::new(&self.level_string)?.run(self.inputs.iter().copied(), self.inputs.len() as u32))
+    }
+}
+
+fn split_line(bytes: &[u8]) -> Option<(&str, &[u8])> {
+    let end = bytes.iter().position(|byte|*byte == b'\n')?;
+    Some((std::str::from_utf8(&bytes[..end]).ok()?, &bytes[end+1..]))
+}
+
+fn encode_control(control: Controllable) -> u8 {
+    let horizontal = match control.horizontal {
+        Horizontal::None => 0,
+        Horizontal::Left => 1,
+        Horizontal::Right => 2
+    };
+    let vertical = match control.vertical {
+        Vertical::None => 0,
+        Vertical::Up => 1,
+        Vertical::Down => 2
+    };
+    horizontal | vertical << 2 | (control.pick_up as u8) << 4
+}
+
+fn decode_control(byte: u8) -> Option<Controllable> {
+    Some(Controllable {
+        horizontal: match byte & 0b11 {
+            0 => Horizontal::None,
+            1 => Horizontal::Left,
+            2 => Horizontal::Right,
+            _ => return None
+        },
+        vertical: match byte >> 2 & 0b11 {
+            0 => Vertical::None,
+            1 => Vertical::Up,
+            2 => Vertical::Down,
+            _ => return None
+        },
+        pick_up: match byte >> 4 {
+            0 => false,
+            1 => true,
+            _ => return None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay(inputs: Vec<Controllable>) -> Replay {
+        Replay {
+            level_string: "#P C E#\n".to_string(),
+            inputs
+        }
+    }
+
+    fn decode_err(bytes: &[u8]) -> String {
+        match Replay::decode(bytes) {
+            Ok(_) => panic!("Decoded malformed replay {:?}", String::from_utf8_lossy(bytes)),
+            Err(err) => err
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let inputs = (0..300).map(|tick| Controllable {
+            horizontal: [Horizontal::None, Horizontal::Left, Horizontal::Right][tick / 7 % 3],
+            vertical: [Vertical::None, Vertical::Up, Vertical::Down][tick / 5 % 3],
+            pick_up: tick % 40 == 0
+        }).collect();
+        let original = replay(inputs);
+        let decoded = Replay::decode(&original.encode()).unwrap();
+        assert_eq!(decoded.level_string, original.level_string);
+        assert_eq!(decoded.inputs, original.inputs);
+    }
+
+    #[test]
+    fn empty() {
+        let decoded = Replay::decode(&replay(Vec::new()).encode()).unwrap();
+        assert_eq!(decoded.level_string, "#P C E#\n");
+        assert!(decoded.inputs.is_empty());
+    }
+
+    #[test]
+    fn long_runs() {
+        let held = Controllable { horizontal: Horizontal::Right, vertical: Vertical::Up, pick_up: false };
+        let original = replay(vec![held; 600]);
+        let bytes = original.encode();
+        // Split into runs of 255, 255 and 90
+        assert_eq!(&bytes[bytes.len() - 6..], &[encode_control(held), 255, encode_control(held), 255, encode_control(held), 90]);
+        assert_eq!(Replay::decode(&bytes).unwrap().inputs, original.inputs);
+    }
+
+    #[test]
+    fn malformed() {
+        let valid = replay(vec![Controllable::default(); 3]).encode();
+        assert_eq!(decode_err(b""), "Missing header");
+        assert_eq!(decode_err(b"not-a-replay 1 0.0.1\n"), "Not a replay file");
+        assert_eq!(decode_err(b"cary-replay 99 0.0.1\n"), "Unsupported replay format version 99");
+        assert_eq!(decode_err(b"cary-replay 1\n"), "Missing game version");
+        assert_eq!(decode_err(format!("cary-replay 1 {}\nlots\n", GAME_VERSION).as_bytes()), "Invalid level length");
+        assert_eq!(decode_err(format!("cary-replay 1 {}\n100\n#P C E#\n", GAME_VERSION).as_bytes()), "Level string is truncated");
+        assert_eq!(decode_err(&valid[..valid.len() - 1]), "Inputs are truncated");
+        let mut invalid_input = valid.clone();
+        let last_pair = invalid_input.len() - 2;
+        invalid_input[last_pair] = 0b11;
+        assert_eq!(decode_err(&invalid_input), "Invalid input");
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::{HashMap, VecDeque};
+use hecs::{Entity, EntityBuilder};
+use crate::{World, WorldState, Snapshot, TIME_BETWEEN_UPDATES};
+use crate::math::Vec2;
+use crate::components::*;
+use crate::textures::CHECKPOINT;
+
+/// How far back the player can rewind
+const REWIND_SECONDS: f32 = 5.0;
+
+/// The last few seconds of a world, to scrub backwards through them.
+/// Only entities that change during the game are kept, e.g. those with physics, a path or a signal.
+#[derive(Default)]
+pub struct Rewind {
+    frames: VecDeque<Frame>,
+    /// Entities that were despawned and then brought back by rewinding get new ids
+    respawned: HashMap<Entity, Entity>
+}
+
+struct Frame {
+    state: WorldState,
+    time: f32,
+    /// Length of World::inputs
+    inputs: usize,
+    snapshot: Option<Snapshot>,
+    camera_pos: Vec2,
+    entities: Vec<EntityState>
+}
+
+/// Components of an entity which change, and those needed to spawn it again
+struct EntityState {
+    entity: Entity,
+    pos: Pos,
+    physics: Option<Physics>,
+    child_of: Option<ChildOf>,
+    children: Option<Children>,
+    carryable: Option<Carryable>,
+    player: Option<Player>,
+    cary: Option<Cary>,
+    walking: Option<Walking>,
+    shooter: Option<Shooter>,
+    path: Option<PathFollower>,
+    plate: Option<PressurePlate>,
+    receiver: Option<Receiver>,
+    health: Option<Health>,
+    sprite: Option<Sprite>,
+    killable: Option<Killable>,
+    hazzard: Option<Hazzard>,
+    collider: Option<Collider>,
+    pusher: Option<Pusher>,
+    pushable: Option<Pushable>,
+    remove_on_impact: Option<RemoveOnImpact>
+}
+
+impl Rewind {
+    /// Call before each update
+    pub fn record(&mut self, world: &World) {
+        let mut entities = if self.frames.len() >= (REWIND_SECONDS / TIME_BETWEEN_UPDATES) as usize {
+            // The oldest frame is forgotten, its buffer can be reused
+            let mut entities = self.frames.pop_front().unwrap().entities;
+            entities.clear();
+            entities
+        } else {
+            Vec::new()
+        };
+        for (entity, entity_ref) in world.entities.iter() {
+            if !changes(&entity_ref) {
+                continue
+            }
+            let pos = match get(&entity_ref) {
+                Some(pos) => pos,
+                None => continue
+            };
+            entities.push(EntityState {
+                entity,
+                pos,
+                physics: get(&entity_ref),
+                child_of: get(&entity_ref),
+                children: get(&entity_ref),
+                carryable: get(&entity_ref),
+                player: get(&entity_ref),
+                cary: get(&entity_ref),
+                walking: get(&entity_ref),
+                shooter: get(&entity_ref),
+                path: get(&entity_ref),
+                plate: get(&entity_ref),
+                receiver: get(&entity_ref),
+                health: get(&entity_ref),
+                sprite: get(&entity_ref),
+                killable: get(&entity_ref),
+                hazzard: get(&entity_ref),
+                collider: get(&entity_ref),
+                pusher: get(&entity_ref),
+                pushable: get(&entity_ref),
+                remove_on_impact: get(&entity_ref)
+            });
+        }
+        self.frames.push_back(Frame {
+            state: world.state,
+            time: world.time,
+            inputs: world.inputs.len(),
+            snapshot: world.snapshot.clone(),
+            camera_pos: world.camera.pos,
+            entities
+        });
+    }
+
+    /// Puts the world into the state of the last recorded frame and forgets that frame.
+    /// Returns false if there's nothing left to rewind.
+    pub fn step_back(&mut self, world: &mut World) -> bool {
+        let frame = match self.frames.pop_back() {
+            Some(frame) => frame,
+            None => return false
+        };
+
+        // Entities spawned after the frame, i.e. bullets
+        let recorded: Vec<_> = frame.entities.iter().map(|state|self.resolve(state.entity)).collect();
+        let to_despawn: Vec<_> = world.entities.iter()
+            .filter(|(_, entity_ref)|changes(entity_ref))
+            .map(|(entity, _)|entity)
+            .filter(|entity|!recorded.contains(entity))
+            .collect();
+        for entity in to_despawn {
+            world.despawn(entity);
+        }
+
+        // Entities despawned after the frame. Spawn them first, so references to them can be resolved.
+        for state in &frame.entities {
+            let entity = self.resolve(state.entity);
+            if !world.entities.contains(entity) {
+                let mut builder = EntityBuilder::new();
+                builder.add(state.pos.clone());
+                add(&mut builder, &state.killable);
+                add(&mut builder, &state.hazzard);
+                add(&mut builder, &state.collider);
+                add(&mut builder, &state.pusher);
+                add(&mut builder, &state.walking);
+                add(&mut builder, &state.pushable);
+                add(&mut builder, &state.remove_on_impact);
+                let respawned = world.entities.spawn(builder.build());
+                self.respawned.insert(entity, respawned);
+            }
+        }
+
+        for state in frame.entities {
+            let entity = self.resolve(state.entity);
+            // Interpolate backwards from where the entity is now
+            let prev_interpol = world.entities.get::<Pos>(entity).unwrap().curr;
+            *world.entities.get_mut::<Pos>(entity).unwrap() = Pos { curr: state.pos.curr, prev_interpol };
+            set(world, entity, state.physics);
+            set(world, entity, state.carryable);
+            set(world, entity, state.cary);
+            set(world, entity, state.shooter);
+            set(world, entity, state.path);
+            set(world, entity, state.plate);
+            set(world, entity, state.receiver);
+            // Doors and retractable spikes
+            set(world, entity, state.collider);
+            set(world, entity, state.hazzard);
+            set(world, entity, state.health);
+            set(world, entity, state.sprite);
+            set(world, entity, state.child_of.map(|child_of|ChildOf { parent: self.resolve(child_of.parent), ..child_of }));
+            set(world, entity, state.children.map(|children|
+                Children(children.0.iter().map(|child|self.resolve(*child)).collect())));
+            set(world, entity, state.player.map(|player|
+                Player { carrying: player.carrying.map(|carried|self.resolve(carried)), ..player }));
+            world.unindex(entity);
+            world.index(entity);
+        }
+
+        for (entity, (_, sprite)) in world.entities.query::<(&Checkpoint, &mut Sprite)>().iter() {
+            let reached = matches!(&frame.snapshot, Some(snapshot) if snapshot.checkpoint == entity);
+            sprite.tex = if reached { &CHECKPOINT[1..] } else { &CHECKPOINT[..1] };
+        }
+
+        world.state = frame.state;
+        world.time = frame.time;
+        world.inputs.truncate(frame.inputs);
+        world.snapshot = frame.snapshot;
+        world.camera.pos = frame.camera_pos;
+        true
+    }
+
+    /// The current id of an entity which may have been respawned
+    fn resolve(&self, mut entity: Entity) -> Entity {
+        while let Some(respawned) = self.respawned.get(&entity) {
+            entity = *respawned;
+        }
+        entity
+    }
+
+    /// The id an entity had before it was respawned, which is also its id in a freshly loaded level
+    pub fn original(&self, mut entity: Entity) -> Entity {
+        while let Some((&original, _)) = self.respawned.iter().find(|(_, respawned)|**respawned == entity) {
+            entity = original;
+        }
+        entity
+    }
+}
+
+fn changes(entity_ref: &hecs::EntityRef) -> bool {
+    entity_ref.get::<Physics>().is_some() | entity_ref.get::<Shooter>().is_some() | entity_ref.get::<PathFollower>().is_some()
+        | entity_ref.get::<PressurePlate>().is_some() | entity_ref.get::<Receiver>().is_some()
+}
+
+fn get<T: hecs::Component + Clone>(entity_ref: &hecs::EntityRef) -> Option<T> {
+    entity_ref.get::<T>().map(|component|(*component).clone())
+}
+
+fn add<T: hecs::Component + Clone>(builder: &mut EntityBuilder, component: &Option<T>) {
+    if let Some(component) = component {
+        builder.add(component.clone());
+    }
+}
+
+/// Replaces, adds or removes the component
+fn set<T: hecs::Component>(world: &mut World, entity: Entity, component: Option<T>) {
+    match component {
+        Some(component) => {
+            if let Ok(mut current) = world.entities.get_mut::<T>(entity) {
+                *current = component;
+                return
+            }
+            world.entities.insert_one(entity, component).unwrap();
+        },
+        None => {
+            let _ = world.entities.remove_one::<T>(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::level;
+    use crate::components::Controllable;
+    use super::*;
+
+    #[test]
+    fn checkpoint_after_rewinding_an_exit() {
+        let level_string = "\
+            #P#                        #\n\
+            # #C       E  c F         E#\n\
+            ############################\n";
+        let mut world = level::load(level_string).unwrap();
+        // Cary exits after the sibling passed the checkpoint
+        let mut ticks = 0;
+        while world.walkers().len() == 2 {
+            assert!(ticks < 1000);
+            world.update(Controllable::default());
+            ticks += 1;
+        }
+        assert!(world.snapshot.is_some());
+        // Back to before the checkpoint, which respawns Cary
+        while world.snapshot.is_some() {
+            world.step_back();
+        }
+        assert_eq!(world.walkers().len(), 2);
+        while world.snapshot.is_none() {
+            world.update(Controllable::default());
+        }
+
+        let snapshot = world.snapshot.take().unwrap();
+        let mut retry = level::load(level_string).unwrap();
+        retry.restore(&snapshot);
+        assert_eq!(retry.walkers().len(), 2);
+        for _ in 0..2000 {
+            if let WorldState::Victory(..) = retry.state {
+                return
+            }
+            retry.update(Controllable::default());
+        }
+        panic!("Not won after retrying from the checkpoint");
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use hecs::Entity;
+use crate::math::*;
+
+/// Side length of the cells in tiles
+const CELL_SIZE: f32 = 4.0;
+
+/// First and last cell, inclusive
+type CellRange = ((i32, i32), (i32, i32));
+
+/// Uniform grid for finding entities by their bounds without checking all of them.
+/// Only stores which cells an entity touches, so the exact bounds have to be checked afterwards.
+#[derive(Default)]
+pub struct Grid {
+    cells: HashMap<(i32, i32), Vec<Entity>>,
+    ranges: HashMap<Entity, CellRange>
+}
+
+impl Grid {
+    /// Adds the entity, or moves it if it was already added
+    pub fn insert(&mut self, entity: Entity, bounds: Bounds) {
+        let range = cell_range(bounds);
+        if self.ranges.get(&entity) == Some(&range) {
+            return
+        }
+        self.remove(entity);
+        for cell in cells(range) {
+            self.cells.entry(cell).or_default().push(entity);
+        }
+        self.ranges.insert(entity, range);
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some(range) = self.ranges.remove(&entity) {
+            for cell in cells(range) {
+                if let Some(entities) = self.cells.get_mut(&cell) {
+                    entities.retain(|other|*other != entity);
+                }
+            }
+        }
+    }
+
+    /// Entities that might overlap the bounds, sorted so that results don't depend on the order of insertion
+    pub fn query(&self, bounds: Bounds) -> Vec<Entity> {
+        let mut entities = Vec::new();
+        for cell in cells(cell_range(bounds)) {
+            if let Some(cell_entities) = self.cells.get(&cell) {
+                entities.extend_from_slice(cell_entities);
+            }
+        }
+        entities.sort();
+        entities.dedup();
+        entities
+    }
+}
+
+fn cell_range(bounds: Bounds) -> CellRange {
+    let cell = |pos: Vec2|((pos.0 / CELL_SIZE).floor() as i32, (pos.1 / CELL_SIZE).floor() as i32);
+    (cell(bounds.min), cell(bounds.max))
+}
+
+fn cells(((min_x, min_y), (max_x, max_y)): CellRange) -> impl Iterator<Item=(i32, i32)> {
+    (min_x..=max_x).flat_map(move |x|(min_y..=max_y).map(move |y|(x, y)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(count: usize) -> Vec<Entity> {
+        let mut world = hecs::World::new();
+        (0..count).map(|_|world.spawn(())).collect()
+    }
+
+    fn bounds(min: (f32, f32), max: (f32, f32)) -> Bounds {
+        Bounds { min: Vec2(min.0, min.1), max: Vec2(max.0, max.1) }
+    }
+
+    #[test]
+    fn across_cells() {
+        let entities = spawn(2);
+        let mut grid = Grid::default();
+        // Spans four cells
+        grid.insert(entities[0], bounds((3.0, 3.0), (5.0, 5.0)));
+        grid.insert(entities[1], bounds((9.0, 1.0), (10.0, 2.0)));
+        assert_eq!(grid.query(bounds((0.0, 0.0), (1.0, 1.0))), vec![entities[0]]);
+        assert_eq!(grid.query(bounds((7.0, 7.0), (7.5, 7.5))), vec![entities[0]]);
+        assert_eq!(grid.query(bounds((8.5, 0.0), (9.0, 1.0))), vec![entities[1]]);
+        assert_eq!(grid.query(bounds((0.0, 9.0), (1.0, 10.0))), vec![]);
+        // Moving leaves the old cells
+        grid.insert(entities[0], bounds((12.0, 0.0), (13.0, 1.0)));
+        assert_eq!(grid.query(bounds((0.0, 0.0), (1.0, 1.0))), vec![]);
+        assert_eq!(grid.query(bounds((9.0, 0.0), (12.5, 0.5))), vec![entities[0], entities[1]]);
+        grid.remove(entities[1]);
+        assert_eq!(grid.query(bounds((9.0, 0.0), (12.5, 0.5))), vec![entities[0]]);
+    }
+
+    #[test]
+    fn negative_coordinates() {
+        let entities = spawn(2);
+        let mut grid = Grid::default();
+        grid.insert(entities[0], bounds((-0.5, -0.5), (-0.1, -0.1)));
+        grid.insert(entities[1], bounds((-4.5, -9.0), (-4.1, -8.5)));
+        // Rounded down, so -0.5 is not in the same cell as 0.5
+        assert_eq!(grid.query(bounds((0.1, 0.1), (0.5, 0.5))), vec![]);
+        assert_eq!(grid.query(bounds((-3.9, -3.9), (-3.5, -3.5))), vec![entities[0]]);
+        assert_eq!(grid.query(bounds((-8.0, -12.0), (-7.0, -11.0))), vec![entities[1]]);
+        assert_eq!(grid.query(bounds((-3.5, -9.0), (-3.0, -8.0))), vec![]);
+    }
+
+    #[test]
+    fn sorted_and_deduplicated() {
+        let entities = spawn(3);
+        let mut grid = Grid::default();
+        for &entity in entities.iter().rev() {
+            grid.insert(entity, bounds((-6.0, -6.0), (6.0, 6.0)));
+        }
+        assert_eq!(grid.query(bounds((-10.0, -10.0), (10.0, 10.0))), entities);
+    }
+}
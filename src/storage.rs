@@ -0,0 +1,26 @@
+// Small text files that persist between sessions:
+// files in the working directory on native, localStorage entries on the web
+
+#[cfg(not(target_arch="wasm32"))]
+pub fn read(name: &str) -> Option<String> {
+    std::fs::read_to_string(name).ok()
+}
+
+#[cfg(not(target_arch="wasm32"))]
+pub fn write(name: &str, string: &str) {
+    if let Err(err) = std::fs::write(name, string) {
+        println!("Failed to save {}: {}", name, err);
+    }
+}
+
+#[cfg(target_arch="wasm32")]
+pub fn read(name: &str) -> Option<String> {
+    web_sys::window()?.local_storage().ok()??.get_item(name).ok()?
+}
+
+#[cfg(target_arch="wasm32")]
+pub fn write(name: &str, string: &str) {
+    if let Some(storage) = web_sys::window().and_then(|window|window.local_storage().ok().flatten()) {
+        let _ = storage.set_item(name, string);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::Camera;
+use crate::math::Vec2;
+use crate::renderer::{Renderer, Layer, Color, WHITE};
+use crate::textures::{self, TexCoords, TexAnchor};
+
+/// Distance between the bottoms of two lines
+pub const LINE_HEIGHT: f32 = 0.8;
+
+pub const BLACK: Color = (0.0, 0.0, 0.0);
+/// Selected menu entries
+pub const HIGHLIGHT: Color = (1.0, 0.85, 0.3);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right
+}
+
+/// How draw() lays out the text
+#[derive(Copy, Clone, Debug)]
+pub struct Style {
+    /// Which side of each line is at the position
+    pub align: Align,
+    pub color: Color,
+    /// In tiles, longer lines are wrapped at spaces
+    pub max_width: Option<f32>,
+    pub layer: Layer
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            align: Align::Left,
+            color: WHITE,
+            max_width: None,
+            layer: Layer::UI
+        }
+    }
+}
+
+/// Draws the text with the tiny font, with the bottom of the first line at the position.
+/// Each '\n' starts a new line.
+pub fn draw(renderer: &mut Renderer, camera: &Camera, pos: Vec2, text: &str, style: &Style) {
+    for (index, line) in lines(text, style.max_width).into_iter().enumerate() {
+        let mut x = pos.0 - align_offset(line, style.align);
+        let y = pos.1 - index as f32 * LINE_HEIGHT;
+        for symbol in line.chars() {
+            let tex = glyph(symbol);
+            let glyph_width = tex.size.0 / textures::PIXELS_PER_TILE;
+            renderer.draw_tinted(camera, Vec2(x + glyph_width / 2.0, y), TexAnchor::Bottom, tex, style.layer, style.color);
+            x += glyph_width;
+        }
+    }
+}
+
+/// Of the longest line, without wrapping
+pub fn width(text: &str) -> f32 {
+    text.split('\n')
+        .map(|line|line.chars().map(|symbol|glyph(symbol).size.0 / textures::PIXELS_PER_TILE).sum())
+        .fold(0.0, f32::max)
+}
+
+/// Of all lines, when wrapped the same way as by draw()
+pub fn height(text: &str, max_width: Option<f32>) -> f32 {
+    lines(text, max_width).len() as f32 * LINE_HEIGHT
+}
+
+/// How far left of the position the line starts
+fn align_offset(line: &str, align: Align) -> f32 {
+    match align {
+        Align::Left => 0.0,
+        Align::Center => width(line) / 2.0,
+        Align::Right => width(line)
+    }
+}
+
+/// Splits at each '\n' and wraps at the last space that keeps lines within max_width.
+/// A single word that is too long gets a line of its own, spaces at the end of a line don't make it wrap.
+pub fn lines(text: &str, max_width: Option<f32>) -> Vec<&str> {
+    let max_width = max_width.unwrap_or(f32::INFINITY);
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut start = 0;
+        // End of the last word that still fit
+        let mut end = 0;
+        for (word_end, _) in paragraph.match_indices(' ').chain(std::iter::once((paragraph.len(), ""))) {
+            if (end > start) && (width(paragraph[start..word_end].trim_end()) > max_width) {
+                lines.push(paragraph[start..end].trim_end());
+                start = end + 1;
+            }
+            end = word_end;
+        }
+        lines.push(&paragraph[start..]);
+    }
+    lines
+}
+
+/// The font contains the printable ASCII characters, others are shown as '?'
+fn glyph(symbol: char) -> &'static TexCoords {
+    (symbol as usize).checked_sub(' ' as usize)
+        .and_then(|index|textures::TEXT_TINY_SYMBOL.get(index))
+        .unwrap_or(&textures::TEXT_TINY_SYMBOL['?' as usize - ' ' as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(lines("", None), vec![""]);
+        assert_eq!(lines("aa bb cc", None), vec!["aa bb cc"]);
+        assert_eq!(lines("aa bb cc", Some(width("aa bb"))), vec!["aa bb", "cc"]);
+        assert_eq!(lines("aa bb cc", Some(width("aa"))), vec!["aa", "bb", "cc"]);
+    }
+
+    #[test]
+    fn long_words() {
+        assert_eq!(lines("abcdef gh", Some(width("ab"))), vec!["abcdef", "gh"]);
+        assert_eq!(lines("ab abcdef", Some(width("ab"))), vec!["ab", "abcdef"]);
+        assert_eq!(lines("abcdef", Some(0.0)), vec!["abcdef"]);
+    }
+
+    #[test]
+    fn newlines() {
+        assert_eq!(lines("aa\n\nbb cc", Some(width("bb"))), vec!["aa", "", "bb", "cc"]);
+        assert_eq!(lines("aa\n", None), vec!["aa", ""]);
+        assert_eq!(height("aa\n\nbb cc", Some(width("bb"))), 4.0 * LINE_HEIGHT);
+        assert_eq!(width("aa\nbbbb\nc"), width("bbbb"));
+    }
+
+    #[test]
+    fn trailing_spaces() {
+        assert_eq!(lines("aa  ", Some(width("aa"))), vec!["aa  "]);
+        assert_eq!(lines("aa  bb", Some(width("aa"))), vec!["aa", "bb"]);
+        assert!(width("aa ") > width("aa"));
+    }
+
+    #[test]
+    fn alignment() {
+        assert_eq!(align_offset("abc", Align::Left), 0.0);
+        assert_eq!(align_offset("abc", Align::Center), width("abc") / 2.0);
+        assert_eq!(align_offset("abc", Align::Right), width("abc"));
+        assert_eq!(align_offset("", Align::Right), 0.0);
+    }
+}